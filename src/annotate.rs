@@ -0,0 +1,206 @@
+use std::str::FromStr;
+
+use color_eyre::{
+    Report, Result,
+    eyre::{OptionExt, eyre},
+};
+use image::{Pixel, Rgba, RgbaImage};
+
+use crate::{geometry::Rect, imaging};
+
+#[derive(Clone, Copy)]
+pub struct Arrow {
+    from: (f32, f32),
+    to: (f32, f32),
+}
+
+impl FromStr for Arrow {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let coordinates = s
+            .split(',')
+            .map(|coordinate| coordinate.trim().parse())
+            .collect::<Result<Vec<f32>, _>>()?;
+        let [x1, y1, x2, y2] = coordinates[..] else {
+            return Err(eyre!("Malformed arrow {s}, expected X1,Y1,X2,Y2"));
+        };
+
+        Ok(Arrow {
+            from: (x1, y1),
+            to: (x2, y2),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Label {
+    text: String,
+    position: (i64, i64),
+}
+
+// The last @ splits, so labels can contain their own
+impl FromStr for Label {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let malformed = || eyre!("Malformed text {s}, expected LABEL@X,Y");
+        let (text, position) = s.rsplit_once('@').ok_or_else(malformed)?;
+        let (x, y) = position.split_once(',').ok_or_else(malformed)?;
+        Ok(Label {
+            text: text.to_string(),
+            position: (x.trim().parse()?, y.trim().parse()?),
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Color(pub Rgba<u8>);
+
+impl FromStr for Color {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let channel = |index: usize| {
+            let channel = hex
+                .get(index * 2..index * 2 + 2)
+                .ok_or_eyre("Short color")?;
+            Ok::<_, Report>(u8::from_str_radix(channel, 16)?)
+        };
+        let alpha = match hex.len() {
+            6 => 255,
+            8 => channel(3)?,
+            _ => return Err(eyre!("Malformed color {s}, expected #RRGGBB or #RRGGBBAA")),
+        };
+
+        Ok(Color(Rgba([channel(0)?, channel(1)?, channel(2)?, alpha])))
+    }
+}
+
+pub struct Annotations<'a> {
+    pub arrows: &'a [Arrow],
+    pub boxes: &'a [Rect],
+    pub labels: &'a [Label],
+    pub color: Color,
+    pub width: f32,
+}
+
+impl Annotations<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.arrows.is_empty() && self.boxes.is_empty() && self.labels.is_empty()
+    }
+}
+
+// Every pixel within half the width of the segment is painted, which also rounds the ends
+fn line(
+    image: &mut RgbaImage,
+    (x1, y1): (f32, f32),
+    (x2, y2): (f32, f32),
+    width: f32,
+    color: Rgba<u8>,
+) {
+    let radius = width / 2.0;
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let length = dx * dx + dy * dy;
+    let clamp = |value: f32, max: u32| value.clamp(0.0, max as f32) as u32;
+    let left = clamp(x1.min(x2) - radius, image.width());
+    let right = clamp(x1.max(x2) + radius + 1.0, image.width());
+    let top = clamp(y1.min(y2) - radius, image.height());
+    let bottom = clamp(y1.max(y2) + radius + 1.0, image.height());
+    for y in top..bottom {
+        for x in left..right {
+            let (px, py) = (x as f32 - x1, y as f32 - y1);
+            let along = match length {
+                0.0 => 0.0,
+                length => ((px * dx + py * dy) / length).clamp(0.0, 1.0),
+            };
+            let (ex, ey) = (px - along * dx, py - along * dy);
+            if ex * ex + ey * ey <= radius * radius {
+                image.get_pixel_mut(x, y).blend(&color);
+            }
+        }
+    }
+}
+
+fn arrow(image: &mut RgbaImage, arrow: Arrow, width: f32, color: Rgba<u8>) {
+    const HEAD_ANGLE: f32 = 0.45;
+    line(image, arrow.from, arrow.to, width, color);
+    let (x, y) = arrow.to;
+    let angle = (arrow.from.1 - y).atan2(arrow.from.0 - x);
+    let head = width * 5.0;
+    for side in [-HEAD_ANGLE, HEAD_ANGLE] {
+        let end = (
+            x + head * (angle + side).cos(),
+            y + head * (angle + side).sin(),
+        );
+        line(image, arrow.to, end, width, color);
+    }
+}
+
+fn outline(image: &mut RgbaImage, rect: Rect, width: f32, color: Rgba<u8>) {
+    let (left, top) = (rect.x as f32, rect.y as f32);
+    let (right, bottom) = (left + rect.width as f32, top + rect.height as f32);
+    let corners = [(left, top), (right, top), (right, bottom), (left, bottom)];
+    for (index, &corner) in corners.iter().enumerate() {
+        line(image, corner, corners[(index + 1) % 4], width, color);
+    }
+}
+
+// Coordinates are in pixels of the final capture
+pub fn annotate(image: &mut RgbaImage, annotations: &Annotations) -> Result<()> {
+    let Color(color) = annotations.color;
+    for &rect in annotations.boxes {
+        outline(image, rect, annotations.width, color);
+    }
+
+    for &spec in annotations.arrows {
+        arrow(image, spec, annotations.width, color);
+    }
+
+    if !annotations.labels.is_empty() {
+        const TEXT_SIZE: f32 = 28.0;
+        let font = imaging::load_font()?;
+        for label in annotations.labels {
+            imaging::draw_text(image, &font, &label.text, label.position, TEXT_SIZE, color);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrows() {
+        let arrow: Arrow = "1, 2.5,3,4".parse().unwrap();
+        assert_eq!((arrow.from, arrow.to), ((1.0, 2.5), (3.0, 4.0)));
+        assert!("1,2,3".parse::<Arrow>().is_err());
+        assert!("1,2,3,x".parse::<Arrow>().is_err());
+    }
+
+    #[test]
+    fn labels_split_on_the_last_at() {
+        let label: Label = "me@host@10, -5".parse().unwrap();
+        assert_eq!((label.text.as_str(), label.position), ("me@host", (10, -5)));
+        assert!("no position".parse::<Label>().is_err());
+        assert!("text@10".parse::<Label>().is_err());
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            "#ff8000".parse::<Color>().unwrap().0,
+            Rgba([255, 128, 0, 255])
+        );
+        assert_eq!(
+            "ff800080".parse::<Color>().unwrap().0,
+            Rgba([255, 128, 0, 128])
+        );
+        for malformed in ["#fff", "#gg0000", "#ff00000"] {
+            assert!(malformed.parse::<Color>().is_err(), "{malformed:?} parsed");
+        }
+    }
+}
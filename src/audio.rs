@@ -0,0 +1,123 @@
+use std::fmt::Write as _;
+
+use clap::Subcommand;
+use color_eyre::{Result, eyre::eyre};
+use serde_json::Value;
+
+use crate::command::{self, not_found, run_command, run_command_with_stdio};
+
+#[derive(Subcommand)]
+pub enum AudioAction {
+    // Picks the default output and moves everything playing over to it
+    Switch {
+        #[arg(long, env = "AUDIO_PICKER", default_value = "fuzzel --dmenu")]
+        picker: String,
+    },
+}
+
+struct Sink {
+    // What set-default takes, the sink name for pactl and the object id for wpctl
+    id: String,
+    description: String,
+}
+
+fn pactl_json(kind: &str) -> Result<Vec<Value>> {
+    let output = run_command_with_stdio("pactl", ["--format=json", "list", kind], true, None)?;
+    match serde_json::from_slice(&output)? {
+        Value::Array(items) => Ok(items),
+        _ => Err(eyre!("Unexpected pactl output")),
+    }
+}
+
+// pipewire-pulse answers pactl too, so this covers both servers
+fn pactl_sinks() -> Result<Vec<Sink>> {
+    Ok(pactl_json("sinks")?
+        .iter()
+        .filter_map(|sink| {
+            Some(Sink {
+                id: sink["name"].as_str()?.to_string(),
+                description: sink["description"].as_str()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+fn pactl_switch(sink: &Sink) -> Result<()> {
+    run_command("pactl", ["set-default-sink", &sink.id])?;
+    for input in pactl_json("sink-inputs")? {
+        let Some(index) = input["index"].as_u64() else {
+            continue;
+        };
+
+        run_command("pactl", ["move-sink-input", &index.to_string(), &sink.id])?;
+    }
+
+    Ok(())
+}
+
+// The Sinks section of wpctl status is a tree, with lines like " │  *   46. Speakers [vol: 0.40]"
+fn wpctl_sinks() -> Result<Vec<Sink>> {
+    let status = String::from_utf8(run_command_with_stdio("wpctl", ["status"], true, None)?)?;
+    Ok(status
+        .lines()
+        .skip_while(|line| !line.contains("Sinks:"))
+        .skip(1)
+        .map(|line| line.trim_start_matches(['│', '├', '└', '─', ' ', '*']))
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| {
+            let (id, rest) = line.split_once(". ")?;
+            let description = rest.split(" [vol:").next()?;
+            Some(Sink {
+                id: id.parse::<u32>().ok()?.to_string(),
+                description: description.trim().to_string(),
+            })
+        })
+        .collect())
+}
+
+// WirePlumber moves streams that weren't pinned to a sink along with the default
+fn wpctl_switch(sink: &Sink) -> Result<()> {
+    run_command("wpctl", ["set-default", &sink.id])
+}
+
+fn switch(picker: &str) -> Result<()> {
+    let (sinks, pactl) = match pactl_sinks() {
+        Err(error) if not_found(&error) => (wpctl_sinks()?, false),
+        result => (result?, true),
+    };
+    if sinks.is_empty() {
+        return Err(eyre!("No audio outputs found"));
+    }
+
+    // Numbered, since two identical devices have the same description
+    let mut choices = String::new();
+    for (number, sink) in sinks.iter().enumerate() {
+        writeln!(choices, "{number}\t{}", sink.description)?;
+    }
+
+    let picked = command::pick(picker, &choices)?;
+    let sink = picked
+        .split_once('\t')
+        .and_then(|(number, _)| sinks.get(number.parse::<usize>().ok()?))
+        .ok_or_else(|| eyre!("Unexpected choice {picked}"))?;
+    match pactl {
+        true => pactl_switch(sink)?,
+        false => wpctl_switch(sink)?,
+    }
+
+    let args = [
+        "Audio output",
+        &sink.description,
+        "-i",
+        "audio-speakers",
+        "-t",
+        "3000",
+    ];
+    run_command("notify-send", args)
+}
+
+pub fn audio(action: AudioAction) -> Result<()> {
+    match action {
+        AudioAction::Switch { picker } => switch(&picker),
+    }
+}
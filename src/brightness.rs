@@ -0,0 +1,122 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Subcommand};
+use color_eyre::{Result, eyre::eyre};
+
+use crate::{
+    command::{not_found, run_command_with_stdio},
+    notification,
+};
+
+const BACKLIGHTS: &str = "/sys/class/backlight";
+
+#[derive(Subcommand)]
+pub enum BrightnessAction {
+    Up,
+    Down,
+    Set { percent: u32 },
+}
+
+#[derive(Args)]
+pub struct BrightnessOptions {
+    #[arg(long, global = true, default_value_t = 5)]
+    step: u32,
+    // The first backlight when unset, see brightnessctl --list
+    #[arg(long, global = true, env = "BRIGHTNESS_DEVICE")]
+    device: Option<String>,
+    // Lowest percentage, so the screen never goes fully dark
+    #[arg(long, global = true, env = "BRIGHTNESS_MIN", default_value_t = 1)]
+    min: u32,
+}
+
+// brightnessctl -m prints device,class,current,percent%,max
+fn brightnessctl(device: Option<&str>, args: &[&str]) -> Result<u32> {
+    let mut command = vec!["-m"];
+    if let Some(device) = device {
+        command.extend(["-d", device]);
+    }
+
+    command.extend(args);
+    let output = String::from_utf8(run_command_with_stdio(
+        "brightnessctl",
+        command,
+        true,
+        None,
+    )?)?;
+    output
+        .split(',')
+        .nth(3)
+        .and_then(|percent| percent.strip_suffix('%')?.parse().ok())
+        .ok_or_else(|| eyre!("Unexpected brightnessctl output {output}"))
+}
+
+fn backlight(device: Option<&str>) -> Result<PathBuf> {
+    if let Some(device) = device {
+        return Ok(Path::new(BACKLIGHTS).join(device));
+    }
+
+    let mut devices: Vec<_> = fs::read_dir(BACKLIGHTS)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<_>>()?;
+    devices.sort();
+    devices
+        .into_iter()
+        .next()
+        .ok_or_else(|| eyre!("No backlight in {BACKLIGHTS}"))
+}
+
+fn read_number(path: &Path) -> Result<u32> {
+    Ok(fs::read_to_string(path)?.trim().parse()?)
+}
+
+// Writing needs the udev rule brightnessctl installs, or membership of the video group
+fn sysfs(device: Option<&str>, percent: Option<u32>) -> Result<u32> {
+    let backlight = backlight(device)?;
+    let max = read_number(&backlight.join("max_brightness"))?;
+    if let Some(percent) = percent {
+        let value = (u64::from(max) * u64::from(percent)).div_ceil(100);
+        fs::write(backlight.join("brightness"), value.to_string())?;
+    }
+
+    let current = read_number(&backlight.join("brightness"))?;
+    Ok((f64::from(current) * 100.0 / f64::from(max)).round() as u32)
+}
+
+fn current(device: Option<&str>) -> Result<u32> {
+    match brightnessctl(device, &["info"]) {
+        Err(error) if not_found(&error) => sysfs(device, None),
+        result => result,
+    }
+}
+
+fn set(device: Option<&str>, percent: u32) -> Result<u32> {
+    match brightnessctl(device, &["set", &format!("{percent}%")]) {
+        Err(error) if not_found(&error) => sysfs(device, Some(percent)),
+        result => result,
+    }
+}
+
+pub fn brightness(action: BrightnessAction, options: BrightnessOptions) -> Result<()> {
+    let device = options.device.as_deref();
+    let target = match action {
+        BrightnessAction::Up => current(device)? + options.step,
+        BrightnessAction::Down => current(device)?.saturating_sub(options.step),
+        BrightnessAction::Set { percent } => percent,
+    };
+
+    let percent = set(device, target.clamp(options.min, 100))?;
+    let icon = match percent {
+        0..=33 => "display-brightness-low",
+        34..=66 => "display-brightness-medium",
+        _ => "display-brightness-high",
+    };
+    notification::osd(
+        &format!("Brightness {percent}%"),
+        "brightness",
+        icon,
+        percent,
+    )
+}
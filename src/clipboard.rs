@@ -0,0 +1,199 @@
+use std::{fmt, fs::File, os::fd::AsRawFd, path::PathBuf, process};
+
+use clap::Subcommand;
+use color_eyre::Result;
+use wl_clipboard_rs::copy::{ClipboardType, MimeType, Options, PreparedCopy, Source};
+
+use crate::command::run_command_with_stdio;
+
+mod history;
+mod image;
+mod sync;
+
+#[derive(Subcommand)]
+pub enum ClipboardAction {
+    // Keeps a history of everything copied until it's stopped, for a compositor autostart
+    Watch {
+        #[arg(long, default_value_t = 750)]
+        max_entries: usize,
+        // Seconds after which copied secrets are cleared from the clipboard
+        #[arg(long)]
+        expire: Option<u64>,
+    },
+    // What watch runs for each copy
+    #[command(hide = true)]
+    Store {
+        #[arg(long, default_value_t = 750)]
+        max_entries: usize,
+        #[arg(long)]
+        expire: Option<u64>,
+    },
+    // What store leaves behind for a secret
+    #[command(hide = true)]
+    Expire {
+        checksum: String,
+        #[arg(long)]
+        after: u64,
+    },
+    // Copies an entry from the history again
+    Pick {
+        #[arg(long, env = "CLIPBOARD_PICKER", default_value = "fuzzel --dmenu")]
+        picker: String,
+    },
+    Clear,
+    // Saves an image from the clipboard like a screenshot, or views it or reads its text
+    Image {
+        // The default
+        #[arg(long, conflicts_with_all = ["view", "ocr"])]
+        save: bool,
+        #[arg(long, conflicts_with = "ocr")]
+        view: bool,
+        #[arg(long)]
+        ocr: bool,
+        #[arg(long, env = "SCREENSHOT_DIR")]
+        dir: Option<PathBuf>,
+        #[arg(
+            long,
+            env = "SCREENSHOT_FILENAME_TEMPLATE",
+            default_value = "screenshot-%Y-%m-%d-%H:%M:%S"
+        )]
+        filename_template: String,
+    },
+    // Copies the primary selection into the clipboard, or keeps doing so with --watch
+    Sync {
+        #[arg(long)]
+        watch: bool,
+    },
+    // Copies the clipboard into the primary selection, for a middle click paste
+    Swap,
+}
+
+#[derive(Clone, Copy)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selection::Clipboard => write!(f, "clipboard"),
+            Selection::Primary => write!(f, "primary"),
+        }
+    }
+}
+
+pub fn clipboard(action: ClipboardAction) -> Result<()> {
+    match action {
+        ClipboardAction::Watch {
+            max_entries,
+            expire,
+        } => history::watch(max_entries, expire),
+        ClipboardAction::Store {
+            max_entries,
+            expire,
+        } => history::store(max_entries, expire),
+        ClipboardAction::Expire { checksum, after } => history::expire(&checksum, after),
+        ClipboardAction::Pick { picker } => history::pick(&picker),
+        ClipboardAction::Clear => history::clear(),
+        ClipboardAction::Image {
+            view,
+            ocr,
+            dir,
+            filename_template,
+            ..
+        } => image::image(view, ocr, dir.as_deref(), &filename_template),
+        ClipboardAction::Sync { watch: true } => sync::watch(),
+        ClipboardAction::Sync { watch: false } => {
+            sync::mirror(Selection::Primary, Selection::Clipboard)
+        }
+        ClipboardAction::Swap => sync::mirror(Selection::Clipboard, Selection::Primary),
+    }
+}
+
+// Some electron apps refuse pastes without an explicit type
+pub const TEXT: &str = "text/plain;charset=utf-8";
+
+pub fn copy(bytes: &[u8], mime: &str) -> Result<()> {
+    copy_to(bytes, mime, Selection::Clipboard)
+}
+
+pub fn copy_to(bytes: &[u8], mime: &str, selection: Selection) -> Result<()> {
+    // Compositors without wlr-data-control still work through wl-copy
+    let Ok(prepared) = prepare(bytes, mime, selection) else {
+        let mut args = vec!["--type", mime];
+        if let Selection::Primary = selection {
+            args.push("--primary");
+        }
+
+        run_command_with_stdio("wl-copy", args, true, Some(bytes))?;
+        return Ok(());
+    };
+
+    serve_in_background(prepared)
+}
+
+pub fn copy_text(text: &str) -> Result<()> {
+    copy(text.as_bytes(), TEXT)
+}
+
+// Text when the selection offers it, otherwise the first image
+pub fn paste(selection: Selection) -> Result<Option<(Vec<u8>, String)>> {
+    let mut args = vec!["--list-types"];
+    if let Selection::Primary = selection {
+        args.push("--primary");
+    }
+
+    let types = String::from_utf8(run_command_with_stdio(
+        "wl-paste",
+        args.clone(),
+        true,
+        None,
+    )?)?;
+    let find = |prefix: &str| types.lines().find(|mime| mime.starts_with(prefix));
+    let Some(mime) = find("text/plain")
+        .or_else(|| find("text/"))
+        .or_else(|| find("image/"))
+    else {
+        return Ok(None);
+    };
+
+    args[0] = "--type";
+    args.insert(1, mime);
+    let bytes = run_command_with_stdio("wl-paste", args, true, None)?;
+    Ok(Some((bytes, mime.to_string())))
+}
+
+fn prepare(bytes: &[u8], mime: &str, selection: Selection) -> Result<PreparedCopy> {
+    let mut options = Options::new();
+    options.foreground(true);
+    if let Selection::Primary = selection {
+        options.clipboard(ClipboardType::Primary);
+    }
+
+    Ok(options.prepare_copy(
+        Source::Bytes(bytes.into()),
+        MimeType::Specific(mime.to_string()),
+    )?)
+}
+
+// The selection dies with its owner, so like wl-copy a forked child keeps serving it after we exit
+fn serve_in_background(prepared: PreparedCopy) -> Result<()> {
+    let null = File::options().read(true).write(true).open("/dev/null")?;
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error().into()),
+        0 => {
+            // Anyone reading our stdout would otherwise wait for the child too
+            for fd in 0..=2 {
+                unsafe { libc::dup2(null.as_raw_fd(), fd) };
+            }
+
+            let code = match prepared.serve() {
+                Ok(()) => 0,
+                Err(_) => 1,
+            };
+            process::exit(code)
+        }
+        _ => Ok(()),
+    }
+}
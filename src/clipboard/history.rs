@@ -0,0 +1,213 @@
+use std::{
+    cmp::Reverse,
+    env,
+    fmt::Write as _,
+    fs,
+    io::{Read, stdin},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use color_eyre::{Result, eyre::eyre};
+use regex::Regex;
+
+use super::{Selection, TEXT, copy, paste};
+use crate::command::{self, run_command, run_command_with_stdio};
+
+// One file per entry named after its checksum, so copying something again only bumps it to the
+// top. The extension tells text and images apart, the modification time orders them.
+fn history_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("scripts")
+        .join("clipboard-history")
+}
+
+fn mime(extension: &str) -> String {
+    match extension {
+        "txt" => TEXT.to_string(),
+        image => format!("image/{image}"),
+    }
+}
+
+fn types() -> Result<String> {
+    let types = run_command_with_stdio("wl-paste", ["--list-types"], true, None)?;
+    Ok(String::from_utf8(types)?)
+}
+
+// wl-paste picks text when the selection offers it, so that's what its type is
+fn extension(types: &str) -> Option<String> {
+    if types.lines().any(|mime| mime.starts_with("text/")) {
+        return Some("txt".to_string());
+    }
+
+    types
+        .lines()
+        .find_map(|mime| mime.strip_prefix("image/"))
+        .map(str::to_string)
+}
+
+// Private keys, tokens of the common forges and clouds, JWTs and assignments like password=...
+const SECRETS: &str = concat!(
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----",
+    r"|\bgh[pousr]_[A-Za-z0-9]{36}\b",
+    r"|\bglpat-[A-Za-z0-9_-]{20}\b",
+    r"|\bAKIA[0-9A-Z]{16}\b",
+    r"|\bxox[abprs]-[A-Za-z0-9-]{10,}",
+    r"|\bsk-[A-Za-z0-9_-]{20,}",
+    r"|\beyJ[\w-]+\.eyJ[\w-]+\.[\w-]+",
+    r"|(?i)\b(?:password|passwd|secret|api_?key|token)\s*[:=]\s*\S+",
+);
+
+// Password managers either mark their copies or offer KDE's hint next to the text
+fn sensitive(types: &str, contents: &[u8]) -> Result<bool> {
+    if env::var("CLIPBOARD_STATE").is_ok_and(|state| state == "sensitive")
+        || types
+            .lines()
+            .any(|mime| mime == "x-kde-passwordManagerHint")
+    {
+        return Ok(true);
+    }
+
+    let text = String::from_utf8_lossy(contents);
+    Ok(Regex::new(SECRETS)?.is_match(&text))
+}
+
+fn entries() -> Result<Vec<PathBuf>> {
+    let dir = history_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        entries.push((entry.metadata()?.modified()?, entry.path()));
+    }
+
+    entries.sort_by_key(|(modified, _)| Reverse(*modified));
+    Ok(entries.into_iter().map(|(_, path)| path).collect())
+}
+
+// Called by wl-paste for every new selection, with the contents on stdin
+pub fn store(max_entries: usize, expire: Option<u64>) -> Result<()> {
+    // Cleared selections are reported with a nil state
+    if env::var("CLIPBOARD_STATE").is_ok_and(|state| state != "data" && state != "sensitive") {
+        return Ok(());
+    }
+
+    let mut contents = Vec::new();
+    stdin().read_to_end(&mut contents)?;
+    let types = types()?;
+    if sensitive(&types, &contents)? {
+        // A detached child, so wl-paste can report the next copy meanwhile
+        if let Some(after) = expire {
+            let exe = env::current_exe()?;
+            let checksum = format!("{:08x}", crc32fast::hash(&contents));
+            let after = after.to_string();
+            let args = ["clipboard", "expire", &checksum, "--after", &after];
+            command::spawn_detached(exe.to_str().unwrap(), args)?;
+        }
+
+        return Ok(());
+    }
+
+    let Some(extension) = extension(&types) else {
+        return Ok(());
+    };
+
+    if contents.iter().all(u8::is_ascii_whitespace) {
+        return Ok(());
+    }
+
+    let dir = history_dir();
+    fs::create_dir_all(&dir)?;
+    let checksum = crc32fast::hash(&contents);
+    fs::write(dir.join(format!("{checksum:08x}.{extension}")), contents)?;
+    for old in entries()?.iter().skip(max_entries) {
+        fs::remove_file(old)?;
+    }
+
+    Ok(())
+}
+
+pub fn watch(max_entries: usize, expire: Option<u64>) -> Result<()> {
+    let exe = env::current_exe()?;
+    let max_entries = max_entries.to_string();
+    let mut args = vec![
+        "--watch",
+        exe.to_str().unwrap(),
+        "clipboard",
+        "store",
+        "--max-entries",
+        &max_entries,
+    ];
+    let expire = expire.map(|expire| expire.to_string());
+    if let Some(expire) = &expire {
+        args.extend(["--expire", expire]);
+    }
+
+    run_command("wl-paste", args)
+}
+
+// Only clears the clipboard if the secret is still what it holds
+pub fn expire(checksum: &str, after: u64) -> Result<()> {
+    thread::sleep(Duration::from_secs(after));
+    let Some((contents, _)) = paste(Selection::Clipboard)? else {
+        return Ok(());
+    };
+
+    if format!("{:08x}", crc32fast::hash(&contents)) == checksum {
+        run_command("wl-copy", ["--clear"])?;
+    }
+
+    Ok(())
+}
+
+fn preview(path: &Path) -> Result<String> {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    if extension != Some("txt") {
+        let size = fs::metadata(path)?.len();
+        return Ok(format!(
+            "[{} image, {} KiB]",
+            extension.unwrap_or_default(),
+            size.div_ceil(1024)
+        ));
+    }
+
+    let text = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    Ok(line.chars().take(100).collect())
+}
+
+// Numbered, since previews of different entries can be the same
+pub fn pick(picker: &str) -> Result<()> {
+    let entries = entries()?;
+    if entries.is_empty() {
+        return Err(eyre!("The clipboard history is empty"));
+    }
+
+    let mut choices = String::new();
+    for (number, entry) in entries.iter().enumerate() {
+        writeln!(choices, "{number}\t{}", preview(entry)?)?;
+    }
+
+    let picked = command::pick(picker, &choices)?;
+    let entry = picked
+        .split_once('\t')
+        .and_then(|(number, _)| entries.get(number.parse::<usize>().ok()?))
+        .ok_or_else(|| eyre!("Unexpected choice {picked}"))?;
+    let extension = entry
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("txt");
+    copy(&fs::read(entry)?, &mime(extension))
+}
+
+pub fn clear() -> Result<()> {
+    match fs::remove_dir_all(history_dir()) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
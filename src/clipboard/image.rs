@@ -0,0 +1,64 @@
+use std::{fs, path::Path};
+
+use chrono::Local;
+use color_eyre::{Result, eyre::OptionExt};
+
+use super::copy_text;
+use crate::{
+    command::{run_command, run_command_with_stdio},
+    geometry::Focus,
+    screenshot, state,
+};
+
+// Browsers offer several formats for one image, png is lossless and the most common
+fn paste_image() -> Result<(Vec<u8>, String)> {
+    let types = run_command_with_stdio("wl-paste", ["--list-types"], true, None)?;
+    let types = String::from_utf8(types)?;
+    let mime = types
+        .lines()
+        .find(|mime| *mime == "image/png")
+        .or_else(|| types.lines().find(|mime| mime.starts_with("image/")))
+        .ok_or_eyre("The clipboard has no image")?;
+    let bytes = run_command_with_stdio("wl-paste", ["--type", mime], true, None)?;
+    Ok((bytes, mime.to_string()))
+}
+
+// Named like screenshots, nothing was focused so the window placeholders stay empty
+fn save(bytes: &[u8], extension: &str, dir: Option<&Path>, template: &str) -> Result<()> {
+    let dir = screenshot::screenshot_dir(dir)?;
+    fs::create_dir_all(&dir)?;
+    let file_name = screenshot::file_name(template, Local::now(), &Focus::default())?;
+    let path = dir.join(format!("{file_name}.{extension}"));
+    fs::write(&path, bytes)?;
+    let message = format!("Saved to {}", path.display());
+    println!("{message}");
+    run_command("notify-send", ["Clipboard", &message, "-t", "6000"])
+}
+
+fn ocr(bytes: &[u8]) -> Result<()> {
+    let text = run_command_with_stdio("tesseract", ["stdin", "stdout"], true, Some(bytes))?;
+    let text = String::from_utf8(text)?;
+    let text = text.trim();
+    copy_text(text)?;
+    println!("{text}");
+    run_command(
+        "notify-send",
+        ["Clipboard", "Recognised text copied", "-t", "6000"],
+    )
+}
+
+pub fn image(view: bool, ocr_text: bool, dir: Option<&Path>, template: &str) -> Result<()> {
+    let (bytes, mime) = paste_image()?;
+    let extension = mime.strip_prefix("image/").unwrap_or("png");
+    if ocr_text {
+        return ocr(&bytes);
+    }
+
+    if !view {
+        return save(&bytes, extension, dir, template);
+    }
+
+    let path = state::runtime_path(&format!("clipboard-image.{extension}"));
+    fs::write(&path, &bytes)?;
+    run_command("xdg-open", [path.to_str().unwrap()])
+}
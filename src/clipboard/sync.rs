@@ -0,0 +1,29 @@
+use std::env;
+
+use color_eyre::{Result, eyre::eyre};
+
+use super::{Selection, copy_to, paste};
+use crate::command::run_command;
+
+pub fn mirror(from: Selection, to: Selection) -> Result<()> {
+    // wl-paste --watch also runs us when the selection is cleared
+    if env::var("CLIPBOARD_STATE").is_ok_and(|state| state != "data") {
+        return Ok(());
+    }
+
+    let (bytes, mime) = paste(from)?.ok_or_else(|| eyre!("The {from} selection is empty"))?;
+    copy_to(&bytes, &mime, to)
+}
+
+// Every new primary selection runs a one-shot sync
+pub fn watch() -> Result<()> {
+    let exe = env::current_exe()?;
+    let args = [
+        "--primary",
+        "--watch",
+        exe.to_str().unwrap(),
+        "clipboard",
+        "sync",
+    ];
+    run_command("wl-paste", args)
+}
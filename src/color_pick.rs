@@ -0,0 +1,46 @@
+use std::{env, fs};
+
+use clap::ValueEnum;
+use color_eyre::Result;
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::{
+    clipboard,
+    command::{run_command, run_command_with_stdio},
+    geometry, imaging,
+    screenshot::ImageFormat,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ColorFormat {
+    Hex,
+    Rgb,
+}
+
+pub fn color_pick(format: ColorFormat) -> Result<()> {
+    let point = geometry::slurp(["-p"], None)?;
+    let pixel = run_command_with_stdio("grim", ["-g", &point, "-"], true, None)?;
+    let Rgb([red, green, blue]) = *imaging::decode(&pixel)?.to_rgb8().get_pixel(0, 0);
+    let color = match format {
+        ColorFormat::Hex => format!("#{red:02x}{green:02x}{blue:02x}"),
+        ColorFormat::Rgb => format!("rgb({red}, {green}, {blue})"),
+    };
+
+    clipboard::copy_text(&color)?;
+    let swatch = RgbImage::from_pixel(64, 64, Rgb([red, green, blue]));
+    let swatch = imaging::encode(&DynamicImage::ImageRgb8(swatch), ImageFormat::Png, None)?;
+    let swatch_path = env::temp_dir().join("scripts-color.png");
+    fs::write(&swatch_path, swatch)?;
+    run_command(
+        "notify-send",
+        [
+            "Color picker",
+            &format!("Copied {color} to clipboard"),
+            "-t",
+            "6000",
+            "-i",
+            swatch_path.to_str().unwrap(),
+        ],
+    )?;
+    Ok(())
+}
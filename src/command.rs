@@ -0,0 +1,220 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::{Result, eyre::OptionExt};
+
+use crate::geometry::Cancelled;
+
+#[derive(Debug)]
+pub struct CommandFailed {
+    command: String,
+    status: ExitStatus,
+    output: Option<String>,
+}
+
+impl CommandFailed {
+    pub fn new(command: &str, status: ExitStatus) -> Self {
+        CommandFailed {
+            command: command.to_string(),
+            status,
+            output: None,
+        }
+    }
+}
+
+impl fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let CommandFailed {
+            command, status, ..
+        } = self;
+        match &self.output {
+            Some(output) => write!(
+                f,
+                "Command {command} exited with exit status {status} and output {output}"
+            ),
+            None => write!(f, "Command {command} exited with exit status {status}"),
+        }
+    }
+}
+
+impl Error for CommandFailed {}
+
+pub fn run_command<'a>(command: &'a str, args: impl IntoIterator<Item = &'a str>) -> Result<()> {
+    run_command_with_stdio(command, args, false, None).map(|_| ())
+}
+
+pub fn run_command_with_stdio<'a>(
+    command: &'a str,
+    args: impl IntoIterator<Item = &'a str>,
+    pipe_stdout: bool,
+    stdin: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    let stdout = match pipe_stdout {
+        true => Stdio::piped(),
+        false => Stdio::inherit(),
+    };
+
+    let mut cmd = Command::new(command)
+        .args(args)
+        .stdout(stdout)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(stdin) = stdin {
+        cmd.stdin.take().unwrap().write_all(stdin)?;
+    }
+
+    let out = cmd.wait_with_output()?;
+    if !out.status.success() {
+        let output = pipe_stdout
+            .then(|| String::from_utf8(out.stdout).ok())
+            .flatten();
+        return Err(CommandFailed {
+            command: command.to_string(),
+            status: out.status,
+            output,
+        }
+        .into());
+    }
+
+    Ok(out.stdout)
+}
+
+// The picker reads the choices from stdin like dmenu and fzf do
+pub fn pick(picker: &str, choices: &str) -> Result<String> {
+    let mut picker = picker.split_whitespace();
+    let command = picker.next().ok_or_eyre("Empty picker command")?;
+    let picked = match run_command_with_stdio(command, picker, true, Some(choices.as_bytes())) {
+        Ok(picked) => String::from_utf8(picked)?.trim().to_string(),
+        Err(error) if error.is::<CommandFailed>() => return Err(Cancelled.into()),
+        Err(error) => return Err(error),
+    };
+    match picked.is_empty() {
+        true => Err(Cancelled.into()),
+        false => Ok(picked),
+    }
+}
+
+// For falling back to another tool when the preferred one isn't installed
+pub fn not_found(error: &color_eyre::Report) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|error| error.kind() == ErrorKind::NotFound)
+}
+
+// The child outlives us, so it must not hold on to our stdio
+pub fn spawn_detached<'a>(
+    command: &'a str,
+    args: impl IntoIterator<Item = &'a str>,
+) -> Result<Child> {
+    let child = Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(child)
+}
+
+// At most jobs items run at once, results keep the order of the items
+pub fn run_parallel<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    run: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+
+                    let result = run(item);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+pub fn timed<T>(run: impl FnOnce() -> Result<T>) -> (Result<T>, Duration) {
+    let start = Instant::now();
+    let result = run();
+    (result, start.elapsed())
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        _ => format!("{}m {:02}s", seconds / 60, seconds % 60),
+    }
+}
+
+// What curl, nix and git print when the network rather than the build is at fault
+const TRANSIENT_ERRORS: &[&str] = &[
+    "Could not resolve host",
+    "Temporary failure in name resolution",
+    "Connection timed out",
+    "Connection reset",
+    "Connection refused",
+    "Failed to connect",
+    "Timeout was reached",
+    "unable to download",
+    "unable to access",
+    "The remote end hung up unexpectedly",
+];
+
+// stderr is still shown, but also kept to tell network failures apart from real ones
+pub fn run_with_retries(command: &str, args: &[&str], retries: u32) -> Result<()> {
+    let mut delay = Duration::from_secs(2);
+    let mut attempt = 0;
+    loop {
+        let mut child = Command::new(command)
+            .args(args)
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stderr = String::new();
+        for line in BufReader::new(child.stderr.take().unwrap()).lines() {
+            let line = line?;
+            eprintln!("{line}");
+            stderr.push_str(&line);
+            stderr.push('\n');
+        }
+
+        let status = child.wait()?;
+        if status.success() {
+            return Ok(());
+        }
+
+        let transient = TRANSIENT_ERRORS.iter().any(|error| stderr.contains(error));
+        if !transient || attempt == retries {
+            return Err(CommandFailed::new(command, status).into());
+        }
+
+        attempt += 1;
+        eprintln!(
+            "Network failure, retrying {command} in {}s ({attempt}/{retries})",
+            delay.as_secs()
+        );
+        thread::sleep(delay);
+        delay *= 2;
+    }
+}
@@ -0,0 +1,88 @@
+use std::{env, fs, path::PathBuf};
+
+use clap::ValueEnum;
+use color_eyre::{Result, eyre::OptionExt};
+
+use crate::{clipboard, command};
+
+// Generated from the Unicode character names: the character, its name and keywords for its group
+const CHARACTERS: &str = include_str!("emoji/characters.tsv");
+const MAX_RECENT: usize = 50;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum EmojiOutput {
+    Copy,
+    // Types it into the focused window with wtype
+    Type,
+}
+
+fn recent_path() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("scripts")
+        .join("emoji-recent")
+}
+
+// Newest first, one character per line
+fn recent() -> Vec<String> {
+    fs::read_to_string(recent_path())
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+fn remember(character: &str) -> Result<()> {
+    let mut recent = recent();
+    recent.retain(|used| used != character);
+    recent.insert(0, character.to_string());
+    recent.truncate(MAX_RECENT);
+    let path = recent_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, recent.join("\n") + "\n")?;
+    Ok(())
+}
+
+// Recently used ones come first, in the order they were used
+fn choices() -> String {
+    let recent = recent();
+    let line = |(character, name, keywords): (&str, &str, &str)| {
+        format!("{character}  {name}  {keywords}\n")
+    };
+    let entries: Vec<_> = CHARACTERS
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some((
+                fields.next()?,
+                fields.next()?,
+                fields.next().unwrap_or_default(),
+            ))
+        })
+        .collect();
+    let recent_entries = recent.iter().filter_map(|used| {
+        entries
+            .iter()
+            .find(|(character, _, _)| character == used)
+            .copied()
+    });
+    let rest = entries
+        .iter()
+        .filter(|(character, _, _)| !recent.iter().any(|used| used == character))
+        .copied();
+    recent_entries.chain(rest).map(line).collect()
+}
+
+pub fn emoji(picker: &str, output: EmojiOutput) -> Result<()> {
+    let picked = command::pick(picker, &choices())?;
+    let character = picked
+        .split_whitespace()
+        .next()
+        .ok_or_eyre("Nothing was picked")?;
+    match output {
+        EmojiOutput::Copy => clipboard::copy_text(character)?,
+        EmojiOutput::Type => command::run_command("wtype", [character])?,
+    }
+
+    remember(character)
+}
@@ -0,0 +1,298 @@
+use std::{env, error::Error, fmt, fs, path::PathBuf, str::FromStr};
+
+use clap::Args;
+use color_eyre::{
+    Report, Result,
+    eyre::{OptionExt, eyre},
+};
+use regex::Regex;
+use swayipc::{Node, NodeType, Output};
+
+use crate::command::{CommandFailed, run_command_with_stdio};
+
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl From<swayipc::Rect> for Rect {
+    fn from(rect: swayipc::Rect) -> Self {
+        Rect {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{} {}x{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+// Parses the slurp/grim "X,Y WxH" format
+impl FromStr for Rect {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let malformed = || eyre!("Malformed geometry {s}");
+        let (position, size) = s.trim().split_once(' ').ok_or_else(malformed)?;
+        let (x, y) = position.split_once(',').ok_or_else(malformed)?;
+        let (width, height) = size.split_once('x').ok_or_else(malformed)?;
+        Ok(Rect {
+            x: x.parse()?,
+            y: y.parse()?,
+            width: width.parse()?,
+            height: height.parse()?,
+        })
+    }
+}
+
+// window_rect is relative to rect and leaves out sway's borders and title bar
+fn window_rect(node: &Node, with_borders: bool) -> Rect {
+    let rect = Rect::from(node.rect);
+    if with_borders {
+        return rect;
+    }
+
+    let window = Rect::from(node.window_rect);
+    Rect {
+        x: rect.x + window.x,
+        y: rect.y + window.y,
+        ..window
+    }
+}
+
+pub fn focused_window(with_borders: bool) -> Result<String> {
+    let sway_tree = swayipc::Connection::new()?.get_tree()?;
+    let node = sway_tree
+        .find_focused(|node| node.node_type == NodeType::Con)
+        .ok_or_eyre("Cannot get focused window")?;
+    Ok(window_rect(&node, with_borders).to_string())
+}
+
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Selection cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+pub fn slurp<'a>(args: impl IntoIterator<Item = &'a str>, stdin: Option<&[u8]>) -> Result<String> {
+    match run_command_with_stdio("slurp", args, true, stdin) {
+        Ok(output) => Ok(String::from_utf8(output)?.trim().to_string()),
+        // slurp fails when the selection is cancelled with escape or a right click
+        Err(error) if error.is::<CommandFailed>() => Err(Cancelled.into()),
+        Err(error) => Err(error),
+    }
+}
+
+#[derive(Args, Default)]
+pub struct SlurpOptions {
+    #[arg(long)]
+    aspect_ratio: Option<String>,
+    #[arg(long)]
+    border_weight: Option<u32>,
+    #[arg(long)]
+    show_dimensions: bool,
+    #[arg(long)]
+    select_output: bool,
+}
+
+impl SlurpOptions {
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(aspect_ratio) = &self.aspect_ratio {
+            args.extend(["-a".to_string(), aspect_ratio.clone()]);
+        }
+
+        if let Some(border_weight) = self.border_weight {
+            args.extend(["-w".to_string(), border_weight.to_string()]);
+        }
+
+        args.extend(self.show_dimensions.then(|| "-d".to_string()));
+        args.extend(self.select_output.then(|| "-o".to_string()));
+        args
+    }
+}
+
+pub fn select_region(slurp_fg: &str, slurp_bg: &str) -> Result<String> {
+    select_region_with(slurp_fg, slurp_bg, &SlurpOptions::default())
+}
+
+pub fn select_region_with(
+    slurp_fg: &str,
+    slurp_bg: &str,
+    options: &SlurpOptions,
+) -> Result<String> {
+    let args = options.args();
+    slurp(
+        ["-c", slurp_fg, "-b", slurp_bg]
+            .into_iter()
+            .chain(args.iter().map(String::as_str)),
+        None,
+    )
+}
+
+#[derive(Default)]
+pub struct Focus {
+    pub app_id: String,
+    pub title: String,
+    pub output: String,
+    pub workspace: String,
+}
+
+pub fn focus() -> Result<Focus> {
+    let mut connection = swayipc::Connection::new()?;
+    let tree = connection.get_tree()?;
+    let window = tree.find_focused(|node| node.node_type == NodeType::Con);
+    let app_id = window
+        .as_ref()
+        .and_then(app_id)
+        .unwrap_or_default()
+        .to_string();
+    let title = window.and_then(|window| window.name).unwrap_or_default();
+    let output = connection
+        .get_outputs()?
+        .into_iter()
+        .find(|output| output.focused)
+        .map(|output| output.name)
+        .unwrap_or_default();
+    let workspace = connection
+        .get_workspaces()?
+        .into_iter()
+        .find(|workspace| workspace.focused)
+        .map(|workspace| workspace.name)
+        .unwrap_or_default();
+    Ok(Focus {
+        app_id,
+        title,
+        output,
+        workspace,
+    })
+}
+
+// Xwayland windows have a class instead of an app_id
+pub fn app_id(node: &Node) -> Option<&str> {
+    node.app_id.as_deref().or_else(|| {
+        node.window_properties
+            .as_ref()
+            .and_then(|properties| properties.class.as_deref())
+    })
+}
+
+pub fn matching_window(pattern: &Regex, with_borders: bool) -> Result<String> {
+    let sway_tree = swayipc::Connection::new()?.get_tree()?;
+    let window = sway_tree
+        .iter()
+        .filter(|node| node.pid.is_some())
+        .find(|node| {
+            app_id(node).is_some_and(|app_id| pattern.is_match(app_id))
+                || node
+                    .name
+                    .as_deref()
+                    .is_some_and(|title| pattern.is_match(title))
+        })
+        .ok_or_else(|| eyre!("No window matches {pattern}"))?;
+    Ok(window_rect(window, with_borders).to_string())
+}
+
+fn last_region_path() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("scripts")
+        .join("last-region")
+}
+
+pub fn save_last_region(region: &str) -> Result<()> {
+    let path = last_region_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, region)?;
+    Ok(())
+}
+
+pub fn last_region() -> Result<String> {
+    fs::read_to_string(last_region_path())
+        .map(|region| region.trim().to_string())
+        .map_err(|_| eyre!("No region has been selected yet"))
+}
+
+// slurp -r restricts the selection to the boxes given on stdin
+pub fn pick_window(slurp_fg: &str, slurp_bg: &str, with_borders: bool) -> Result<String> {
+    let sway_tree = swayipc::Connection::new()?.get_tree()?;
+    let boxes: String = sway_tree
+        .iter()
+        .filter(|node| node.visible == Some(true))
+        .map(|node| {
+            let title = node.name.as_deref().unwrap_or_default();
+            format!("{} {title}\n", window_rect(node, with_borders))
+        })
+        .collect();
+    slurp(
+        ["-r", "-c", slurp_fg, "-b", slurp_bg],
+        Some(boxes.as_bytes()),
+    )
+}
+
+pub fn focused_output() -> Result<Output> {
+    swayipc::Connection::new()?
+        .get_outputs()?
+        .into_iter()
+        .find(|output| output.focused)
+        .ok_or_eyre("Cannot get focused output")
+}
+
+pub fn output(name: Option<String>) -> Result<String> {
+    let Some(name) = name else {
+        return Ok(focused_output()?.name);
+    };
+
+    let outputs = swayipc::Connection::new()?.get_outputs()?;
+    if !outputs.iter().any(|output| output.name == name) {
+        let names: Vec<_> = outputs.into_iter().map(|output| output.name).collect();
+        return Err(eyre!(
+            "Output {name} not found, available outputs: {}",
+            names.join(", ")
+        ));
+    }
+
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_round_trips() {
+        let rect: Rect = " -10,20 300x400\n".parse().unwrap();
+        assert_eq!(
+            (rect.x, rect.y, rect.width, rect.height),
+            (-10, 20, 300, 400)
+        );
+        assert_eq!(rect.to_string(), "-10,20 300x400");
+    }
+
+    #[test]
+    fn rect_parse_errors() {
+        for malformed in [
+            "",
+            "10,20",
+            "10 20x30",
+            "10,20 30",
+            "a,20 30x40",
+            "10,20 30x4.5",
+        ] {
+            assert!(malformed.parse::<Rect>().is_err(), "{malformed:?} parsed");
+        }
+    }
+}
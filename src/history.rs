@@ -0,0 +1,47 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::Write,
+    path::PathBuf,
+};
+
+use color_eyre::{Result, eyre::OptionExt};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub captured: String,
+    pub area: String,
+    pub app_id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+fn history_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("scripts")
+        .join("screenshot-history.jsonl")
+}
+
+// One json object per line, appending never has to rewrite the whole history
+pub fn record(entry: &Entry) -> Result<()> {
+    let path = history_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut file = File::options().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+// Screenshots deleted since are skipped
+pub fn last() -> Result<Entry> {
+    let history = fs::read_to_string(history_path()).unwrap_or_default();
+    history
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+        .find(|entry| entry.path.exists())
+        .ok_or_eyre("No screenshot in history")
+}
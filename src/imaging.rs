@@ -0,0 +1,250 @@
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Cursor,
+};
+
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont, point};
+use color_eyre::Result;
+use image::{
+    DynamicImage, Pixel, Rgba, RgbaImage,
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, pnm::PnmEncoder},
+    imageops::{self, FilterType},
+};
+
+use crate::{command::run_command_with_stdio, screenshot::ImageFormat};
+
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage> {
+    Ok(image::load_from_memory(bytes)?)
+}
+
+pub fn encode(image: &DynamicImage, format: ImageFormat, quality: Option<u8>) -> Result<Vec<u8>> {
+    let mut bytes = Cursor::new(Vec::new());
+    match format {
+        ImageFormat::Png => image.write_with_encoder(PngEncoder::new(&mut bytes))?,
+        // Same default as grim
+        ImageFormat::Jpeg => image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(
+                &mut bytes,
+                quality.unwrap_or(80),
+            ))?,
+        ImageFormat::Ppm => image
+            .to_rgb8()
+            .write_with_encoder(PnmEncoder::new(&mut bytes))?,
+    }
+
+    Ok(bytes.into_inner())
+}
+
+pub fn pixelate(image: &mut DynamicImage, x: u32, y: u32, width: u32, height: u32) {
+    const BLOCK: u32 = 16;
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let pixelated = image
+        .crop_imm(x, y, width, height)
+        .resize_exact(
+            width.div_ceil(BLOCK),
+            height.div_ceil(BLOCK),
+            FilterType::Triangle,
+        )
+        .resize_exact(width, height, FilterType::Nearest);
+    imageops::replace(image, &pixelated, x.into(), y.into());
+}
+
+fn row_hashes(image: &RgbaImage) -> Vec<u64> {
+    image
+        .rows()
+        .map(|row| {
+            let mut hasher = DefaultHasher::new();
+            row.for_each(|pixel| pixel.0.hash(&mut hasher));
+            hasher.finish()
+        })
+        .collect()
+}
+
+// Stacks frames vertically, dropping the rows each frame shares with the bottom of the previous one
+pub fn stitch(frames: &[DynamicImage]) -> DynamicImage {
+    let frames: Vec<_> = frames.iter().map(DynamicImage::to_rgba8).collect();
+    let width = frames
+        .iter()
+        .map(RgbaImage::width)
+        .max()
+        .unwrap_or_default();
+    let mut pieces = Vec::new();
+    let mut previous: Option<Vec<u64>> = None;
+    for frame in &frames {
+        let hashes = row_hashes(frame);
+        let overlap = previous.as_ref().map_or(0, |previous| {
+            (1..=hashes.len().min(previous.len()))
+                .rev()
+                .find(|&overlap| previous[previous.len() - overlap..] == hashes[..overlap])
+                .unwrap_or_default()
+        });
+        pieces.push((frame, overlap as u32));
+        previous = Some(hashes);
+    }
+
+    let height = pieces
+        .iter()
+        .map(|(frame, overlap)| frame.height() - overlap)
+        .sum();
+    let mut stitched = RgbaImage::new(width, height);
+    let mut y = 0;
+    for (frame, overlap) in pieces {
+        let piece = imageops::crop_imm(frame, 0, overlap, frame.width(), frame.height() - overlap);
+        imageops::replace(&mut stitched, &*piece, 0, y);
+        y += i64::from(frame.height() - overlap);
+    }
+
+    DynamicImage::ImageRgba8(stitched)
+}
+
+// Crops away borders that are fully transparent or share the color of the top left corner
+pub fn trim(image: &DynamicImage) -> DynamicImage {
+    let rgba = image.to_rgba8();
+    let Some(&corner) = rgba.pixels().next() else {
+        return image.clone();
+    };
+
+    let is_border = |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        pixel.0[3] == 0 || *pixel == corner
+    };
+    let content = |x| (0..rgba.height()).any(|y| !is_border(x, y));
+    let Some(left) = (0..rgba.width()).find(|&x| content(x)) else {
+        return image.clone();
+    };
+
+    let right = (0..rgba.width()).rev().find(|&x| content(x)).unwrap();
+    let row_content = |y| (left..=right).any(|x| !is_border(x, y));
+    let top = (0..rgba.height()).find(|&y| row_content(y)).unwrap();
+    let bottom = (0..rgba.height()).rev().find(|&y| row_content(y)).unwrap();
+    image.crop_imm(left, top, right - left + 1, bottom - top + 1)
+}
+
+// fc-match resolves whatever sans font the system is configured with
+pub fn load_font() -> Result<FontVec> {
+    let path = run_command_with_stdio("fc-match", ["-f", "%{file}", "sans"], true, None)?;
+    Ok(FontVec::try_from_vec(fs::read(String::from_utf8(path)?)?)?)
+}
+
+pub fn draw_text(
+    image: &mut RgbaImage,
+    font: &FontVec,
+    text: &str,
+    (x, y): (i64, i64),
+    size: f32,
+    color: Rgba<u8>,
+) {
+    let font = font.as_scaled(PxScale::from(size));
+    let mut caret = 0.0;
+    let mut previous = None;
+    for character in text.chars() {
+        let id = font.glyph_id(character);
+        if let Some(previous) = previous {
+            caret += font.kern(previous, id);
+        }
+
+        let glyph = id.with_scale_and_position(size, point(caret, font.ascent()));
+        caret += font.h_advance(id);
+        previous = Some(id);
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+
+        let bounds = outline.px_bounds();
+        outline.draw(|glyph_x, glyph_y, coverage| {
+            let pixel_x = x + bounds.min.x as i64 + i64::from(glyph_x);
+            let pixel_y = y + bounds.min.y as i64 + i64::from(glyph_y);
+            let (Ok(pixel_x), Ok(pixel_y)) = (u32::try_from(pixel_x), u32::try_from(pixel_y))
+            else {
+                return;
+            };
+
+            if pixel_x < image.width() && pixel_y < image.height() {
+                let mut color = color;
+                color.0[3] = (f32::from(color.0[3]) * coverage) as u8;
+                image.get_pixel_mut(pixel_x, pixel_y).blend(&color);
+            }
+        });
+    }
+}
+
+fn text_width(font: &FontVec, text: &str, size: f32) -> f32 {
+    let font = font.as_scaled(PxScale::from(size));
+    text.chars()
+        .map(|character| font.h_advance(font.glyph_id(character)))
+        .sum()
+}
+
+// White text on a translucent dark background, readable on top of any capture
+pub fn label(font: &FontVec, text: &str, size: f32) -> RgbaImage {
+    const PADDING: f32 = 8.0;
+    let height = font.as_scaled(PxScale::from(size)).height();
+    let mut label = RgbaImage::from_pixel(
+        (text_width(font, text, size) + 2.0 * PADDING).ceil() as u32,
+        (height + 2.0 * PADDING).ceil() as u32,
+        Rgba([0, 0, 0, 160]),
+    );
+    let position = (PADDING as i64, PADDING as i64);
+    draw_text(&mut label, font, text, position, size, Rgba([255; 4]));
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    // One pixel wide, every row a different shade so overlaps are unambiguous
+    fn rows(shades: &[u8]) -> DynamicImage {
+        let mut image = RgbaImage::new(1, shades.len() as u32);
+        for (y, &shade) in shades.iter().enumerate() {
+            image.put_pixel(0, y as u32, Rgba([shade, shade, shade, 255]));
+        }
+
+        DynamicImage::ImageRgba8(image)
+    }
+
+    fn shades(image: &DynamicImage) -> Vec<u8> {
+        image.to_rgba8().pixels().map(|pixel| pixel.0[0]).collect()
+    }
+
+    #[test]
+    fn stitch_drops_the_overlap() {
+        let stitched = stitch(&[rows(&[1, 2, 3, 4]), rows(&[3, 4, 5, 6]), rows(&[7, 8])]);
+        assert_eq!(shades(&stitched), [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn stitch_keeps_frames_that_share_nothing() {
+        let stitched = stitch(&[rows(&[1, 2, 3]), rows(&[4, 5]), rows(&[1, 2])]);
+        assert_eq!(shades(&stitched), [1, 2, 3, 4, 5, 1, 2]);
+    }
+
+    #[test]
+    fn stitch_collapses_identical_frames() {
+        let stitched = stitch(&[rows(&[1, 2]), rows(&[1, 2])]);
+        assert_eq!(shades(&stitched), [1, 2]);
+    }
+
+    #[test]
+    fn trim_crops_to_the_content() {
+        let mut image = RgbaImage::from_pixel(6, 5, Rgba([255, 255, 255, 255]));
+        image.put_pixel(2, 1, Rgba([0, 0, 0, 255]));
+        image.put_pixel(3, 3, Rgba([0, 0, 0, 255]));
+        image.put_pixel(5, 0, Rgba([0, 0, 0, 0]));
+        let trimmed = trim(&DynamicImage::ImageRgba8(image));
+        assert_eq!((trimmed.width(), trimmed.height()), (2, 3));
+    }
+
+    #[test]
+    fn trim_leaves_blank_images_alone() {
+        let blank = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        assert_eq!(trim(&blank).width(), 4);
+    }
+}
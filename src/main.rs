@@ -1,227 +1,175 @@
-use std::{
-    env, fs,
-    io::{Read, Write, stdin},
-    iter,
-    path::PathBuf,
-    process::{Command, Stdio},
-};
+mod annotate;
+mod audio;
+mod brightness;
+mod clipboard;
+mod color_pick;
+mod command;
+mod emoji;
+mod geometry;
+mod history;
+mod imaging;
+mod media;
+mod metadata;
+mod mic;
+mod nixos;
+mod notification;
+mod power_menu;
+mod record;
+mod screencopy;
+mod screenshot;
+mod scrollback;
+mod state;
+mod still;
+mod upload;
+mod volume;
 
-use chrono::Local;
 use clap::{Parser, Subcommand};
-use color_eyre::{
-    Result,
-    eyre::{ContextCompat, OptionExt, eyre},
+use color_eyre::Result;
+
+use crate::{
+    audio::AudioAction,
+    brightness::{BrightnessAction, BrightnessOptions},
+    clipboard::ClipboardAction,
+    color_pick::ColorFormat,
+    emoji::EmojiOutput,
+    geometry::Cancelled,
+    media::MediaAction,
+    mic::MicAction,
+    nixos::{NixosAction, NixosOptions},
+    power_menu::PowerMenuOptions,
+    record::{RecordArea, RecordOptions},
+    screenshot::{ScreenshotArea, ScreenshotOptions},
+    scrollback::{ScrollbackAction, ScrollbackOptions},
+    volume::VolumeAction,
 };
 
-use regex::Regex;
-use swayipc::NodeType;
-
 #[derive(Parser)]
 struct Cli {
     #[command(subcommand)]
     script: Script,
+    #[arg(long, global = true)]
+    notify_cancelled: bool,
 }
 
-#[derive(Subcommand)]
-enum NixosAction {
-    Configure {
-        #[arg(long, env = "EDITOR")]
-        editor_name: String,
-        #[arg(long)]
-        update: bool,
-    },
-    Update,
-}
-
+// Parsed once, the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Script {
     Nixos {
         #[command(subcommand)]
         action: NixosAction,
-        #[arg(long, env = "NH_FLAKE")]
-        flake: PathBuf,
+        #[command(flatten)]
+        options: NixosOptions,
     },
 
     Scrollback {
-        #[arg(long, env = "EDITOR")]
-        editor_name: String,
+        #[command(subcommand)]
+        action: Option<ScrollbackAction>,
+        #[command(flatten)]
+        options: ScrollbackOptions,
     },
     Screenshot {
         #[command(subcommand)]
         area: ScreenshotArea,
+        #[command(flatten)]
+        options: ScreenshotOptions,
     },
-}
-
-#[derive(Subcommand)]
-enum ScreenshotArea {
-    Fullscreen,
-    Window,
-    Region {
-        #[arg(long)]
-        slurp_fg: String,
-        #[arg(long)]
-        slurp_bg: String,
+    Record {
+        #[command(subcommand)]
+        area: RecordArea,
+        #[command(flatten)]
+        options: RecordOptions,
+    },
+    Clipboard {
+        #[command(subcommand)]
+        action: ClipboardAction,
+    },
+    ColorPick {
+        #[arg(long, value_enum, default_value = "hex")]
+        format: ColorFormat,
+    },
+    Emoji {
+        #[arg(long, env = "EMOJI_PICKER", default_value = "fuzzel --dmenu")]
+        picker: String,
+        #[arg(long, value_enum, default_value = "copy")]
+        output: EmojiOutput,
+    },
+    PowerMenu {
+        #[command(flatten)]
+        options: PowerMenuOptions,
+    },
+    Volume {
+        #[command(subcommand)]
+        action: VolumeAction,
+        #[arg(long, global = true, default_value_t = 5)]
+        step: u32,
+        // The highest volume up goes to, in percent
+        #[arg(long, global = true, default_value_t = 100)]
+        limit: u32,
+    },
+    Brightness {
+        #[command(subcommand)]
+        action: BrightnessAction,
+        #[command(flatten)]
+        options: BrightnessOptions,
+    },
+    Media {
+        #[command(subcommand)]
+        action: MediaAction,
+        // Any player when unset, see playerctl --list-all
+        #[arg(long, global = true, env = "MEDIA_PLAYER")]
+        player: Option<String>,
+    },
+    Mic {
+        #[command(subcommand)]
+        action: MicAction,
+        // Run through sh after every change
+        #[arg(long, global = true, env = "MIC_LED")]
+        led: Option<String>,
+    },
+    Audio {
+        #[command(subcommand)]
+        action: AudioAction,
     },
 }
 
 fn main() -> Result<()> {
     color_eyre::install()?;
 
-    match Cli::parse().script {
-        Script::Nixos {
-            action:
-                NixosAction::Configure {
-                    editor_name,
-                    update,
-                },
-            flake,
-        } => nixos_configure(editor_name, update, flake),
-        Script::Nixos {
-            action: NixosAction::Update,
-            flake,
-        } => nixos_update(flake),
-        Script::Scrollback { editor_name } => scrollback(editor_name),
-        Script::Screenshot { area } => screenshot(area),
-    }?;
-
-    Ok(())
-}
-
-fn run_command<'a>(command: &'a str, args: impl IntoIterator<Item = &'a str>) -> Result<()> {
-    run_command_with_stdio(command, args, false, None).map(|_| ())
-}
-
-fn run_command_with_stdio<'a>(
-    command: &'a str,
-    args: impl IntoIterator<Item = &'a str>,
-    pipe_stdout: bool,
-    stdin: Option<&[u8]>,
-) -> Result<Vec<u8>> {
-    let stdout = match pipe_stdout {
-        true => Stdio::piped(),
-        false => Stdio::inherit(),
+    let cli = Cli::parse();
+    let result = match cli.script {
+        Script::Nixos { action, options } => nixos::nixos(action, options),
+        Script::Scrollback { action, options } => scrollback::scrollback(action, options),
+        Script::Screenshot { area, options } => screenshot::screenshot(area, options),
+        Script::Record { area, options } => record::record(area, options),
+        Script::Clipboard { action } => clipboard::clipboard(action),
+        Script::ColorPick { format } => color_pick::color_pick(format),
+        Script::Emoji { picker, output } => emoji::emoji(&picker, output),
+        Script::PowerMenu { options } => power_menu::power_menu(options),
+        Script::Volume {
+            action,
+            step,
+            limit,
+        } => volume::volume(action, step, limit),
+        Script::Brightness { action, options } => brightness::brightness(action, options),
+        Script::Media { action, player } => media::media(action, player.as_deref()),
+        Script::Mic { action, led } => mic::mic(action, led.as_deref()),
+        Script::Audio { action } => audio::audio(action),
     };
 
-    let mut cmd = Command::new(command)
-        .args(args)
-        .stdout(stdout)
-        .stdin(Stdio::piped())
-        .spawn()?;
-    if let Some(stdin) = stdin {
-        cmd.stdin.take().unwrap().write_all(stdin)?;
-    }
-
-    let out = cmd.wait_with_output()?;
-    if !out.status.success() {
-        let error_msg = if pipe_stdout && let Ok(stdout) = String::from_utf8(out.stdout) {
-            eyre!(
-                "Command {command} exited with exit status {} and output {stdout}",
-                out.status
-            )
-        } else {
-            eyre!("Command {command} exited with exit status {}", out.status)
-        };
-
-        return Err(error_msg);
-    }
-
-    Ok(out.stdout)
-}
-
-fn nixos_configure(editor_name: String, update: bool, flake: PathBuf) -> Result<()> {
-    env::set_current_dir(&flake)?;
-    run_command(&editor_name, None)?;
-    run_command("git", ["add", "."])?;
-    let args = ["os", "switch", "."]
-        .into_iter()
-        .chain(update.then_some("--update"));
-    run_command("nh", args)?;
-    run_command("git", ["commit", "-a"])?;
-    run_command("git", iter::once("push"))?;
-    Ok(())
-}
-
-fn nixos_update(flake: PathBuf) -> Result<()> {
-    env::set_current_dir(&flake)?;
-    run_command("git", ["add", "."])?;
-    let args = ["os", "switch", ".", "--update"];
-    run_command("nh", args)?;
-    Ok(())
-}
-
-fn screenshot(area: ScreenshotArea) -> Result<()> {
-    let mut path = dirs::picture_dir().wrap_err("Cannot determine pictures dir")?;
-    path.push("screenshots");
-    fs::create_dir_all(&path)?;
-    const FMT: &str = "screenshot-%Y-%m-%d-%H:%M:%S.png";
-    let file_name = Local::now().format(FMT).to_string();
-    path.push(file_name);
-    let grim = |args: Option<&str>| {
-        run_command_with_stdio(
-            "grim",
-            args.into_iter()
-                .flat_map(|region| ["-g", region])
-                .chain(iter::once("-")),
-            true,
-            None,
-        )
-    };
-
-    let bytes = match area {
-        ScreenshotArea::Fullscreen => grim(None),
-        ScreenshotArea::Window => {
-            let sway_tree = swayipc::Connection::new()?.get_tree()?;
-            let rect = sway_tree
-                .find_focused(|node| node.node_type == NodeType::Con)
-                .ok_or_eyre("Cannot get focused window")?
-                .rect;
-            let rect_formatted = format!("{},{} {}x{}", rect.x, rect.y, rect.width, rect.height);
-            grim(Some(&rect_formatted))
+    // Cancelling a selection from a keybind is not an error worth a backtrace
+    if let Err(error) = &result
+        && error.is::<Cancelled>()
+    {
+        if cli.notify_cancelled {
+            command::run_command(
+                "notify-send",
+                ["Cancelled", "Selection cancelled", "-t", "2000"],
+            )?;
         }
-        ScreenshotArea::Region { slurp_fg, slurp_bg } => {
-            let slurp_output =
-                run_command_with_stdio("slurp", ["-c", &slurp_fg, "-b", &slurp_bg], true, None)?;
-            let region = String::from_utf8(slurp_output)?;
-            grim(Some(region.trim()))
-        }
-    }?;
-
-    fs::create_dir_all(path.parent().unwrap())?;
-    fs::write(&path, &bytes)?;
-
-    // wl_cliboard_rs api sucked pretty much
-    run_command_with_stdio("wl-copy", None, true, Some(&bytes))?;
-    //notify-rs was slow for some reason
-    run_command(
-        "notify-send",
-        [
-            "Screenshot",
-            &format!(
-                "File saved as {} and copied to clipboard",
-                path.to_str().unwrap()
-            ),
-            "-t",
-            "6000",
-            "-i",
-            path.to_str().unwrap(),
-        ],
-    )?;
-    Ok(())
-}
-
-fn scrollback(editor_name: String) -> Result<()> {
-    let mut input = String::new();
-    stdin().read_to_string(&mut input)?;
 
-    const CONTROL_SEQUENCES: &str = r"\x1b\[[\x30-\x3F]*[\x20-\x2F]*[\x40-\x7E]";
-    const INDEPENDENT_CONTROL_FUNCTIONS: &str = r"\x1b[\x60-\x7E]";
-    const COMMAND_STRINGS: &str = r"\x1b[\x5F\x50\x5D\x5E][\x08-\x0D\x20-\x7E]*(\x1b\\|\x07)";
-    const CARRIAGE_RETURN: &str = r"\r";
-    let re = &format!(
-        "({CONTROL_SEQUENCES}|{INDEPENDENT_CONTROL_FUNCTIONS}|{COMMAND_STRINGS}|{CARRIAGE_RETURN})"
-    );
+        return Ok(());
+    }
 
-    let str = Regex::new(re)?.replace_all(input.trim(), "");
-    run_command_with_stdio(&editor_name, None, false, Some(str.as_bytes()))?;
-    Ok(())
+    result
 }
@@ -0,0 +1,131 @@
+use std::{thread, time::Duration};
+
+use clap::Subcommand;
+use color_eyre::Result;
+
+use crate::command::{run_command, run_command_with_stdio};
+
+mod art;
+
+#[derive(Subcommand)]
+pub enum MediaAction {
+    PlayPause,
+    Next,
+    Prev,
+    Stop,
+}
+
+impl MediaAction {
+    fn playerctl(&self) -> &'static str {
+        match self {
+            MediaAction::PlayPause => "play-pause",
+            MediaAction::Next => "next",
+            MediaAction::Prev => "previous",
+            MediaAction::Stop => "stop",
+        }
+    }
+}
+
+struct Track {
+    id: String,
+    status: String,
+    title: String,
+    artist: String,
+    album: String,
+    art_url: String,
+}
+
+fn playerctl<'a>(player: Option<&'a str>, args: &[&'a str]) -> Vec<&'a str> {
+    let mut command = Vec::new();
+    if let Some(player) = player {
+        command.extend(["--player", player]);
+    }
+
+    command.extend(args);
+    command
+}
+
+// Tabs don't show up in track metadata, unlike the separators people put in titles
+fn metadata(player: Option<&str>) -> Result<Track> {
+    let format =
+        "{{mpris:trackid}}\t{{status}}\t{{title}}\t{{artist}}\t{{album}}\t{{mpris:artUrl}}";
+    let args = playerctl(player, &["metadata", "--format", format]);
+    let output = String::from_utf8(run_command_with_stdio("playerctl", args, true, None)?)?;
+    let mut fields = output
+        .trim_end_matches('\n')
+        .split('\t')
+        .map(str::to_string);
+    let mut field = || fields.next().unwrap_or_default();
+    Ok(Track {
+        id: field(),
+        status: field(),
+        title: field(),
+        artist: field(),
+        album: field(),
+        art_url: field(),
+    })
+}
+
+// Players update their metadata a moment after skipping, so this waits for the track to change
+fn changed_track(player: Option<&str>, previous: &str) -> Result<Track> {
+    let mut track = metadata(player)?;
+    for _ in 0..10 {
+        if track.id != previous {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+        track = metadata(player)?;
+    }
+
+    Ok(track)
+}
+
+fn notify(track: &Track) -> Result<()> {
+    let summary = match track.title.is_empty() {
+        true => track.status.clone(),
+        false => track.title.clone(),
+    };
+    let body = match (track.artist.as_str(), track.album.as_str()) {
+        ("", album) => album.to_string(),
+        (artist, "") => artist.to_string(),
+        (artist, album) => format!("{artist} — {album}"),
+    };
+    // Missing art shouldn't cost the notification
+    let icon = art::art(&track.art_url)
+        .ok()
+        .flatten()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "multimedia-player".to_string());
+    let args = [
+        &summary,
+        &body,
+        "-h",
+        "string:x-canonical-private-synchronous:media",
+        "-i",
+        &icon,
+        "-t",
+        "4000",
+    ];
+    run_command("notify-send", args)
+}
+
+pub fn media(action: MediaAction, player: Option<&str>) -> Result<()> {
+    let args = playerctl(player, &[action.playerctl()]);
+    match action {
+        MediaAction::Stop => run_command("playerctl", args),
+        // Nothing loaded means nothing to show, not a failure
+        MediaAction::PlayPause => {
+            run_command("playerctl", args)?;
+            match metadata(player) {
+                Ok(track) => notify(&track),
+                Err(_) => Ok(()),
+            }
+        }
+        MediaAction::Next | MediaAction::Prev => {
+            let previous = metadata(player)?;
+            run_command("playerctl", args)?;
+            notify(&changed_track(player, &previous.id)?)
+        }
+    }
+}
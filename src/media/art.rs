@@ -0,0 +1,88 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::Result;
+
+use crate::state;
+
+fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("scripts")
+        .join("album-art")
+}
+
+// File urls escape spaces and anything outside ASCII, like %20 and %C3%A9 for é. Paths don't have
+// to be UTF-8, so the bytes are decoded as they are.
+fn percent_decode(path: &str) -> Vec<u8> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| u8::from_str_radix(str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    decoded
+}
+
+// Local players hand out file urls, streaming ones link to their CDN. Downloads are kept by a
+// checksum of the url, since the same album comes up for every one of its tracks.
+pub fn art(url: &str) -> Result<Option<PathBuf>> {
+    if let Some(path) = url.strip_prefix("file://") {
+        let path = path.strip_prefix("localhost").unwrap_or(path);
+        return Ok(Some(
+            Path::new(OsStr::from_bytes(&percent_decode(path))).to_path_buf(),
+        ));
+    }
+
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Ok(None);
+    }
+
+    let path = cache_dir().join(format!("{:08x}", crc32fast::hash(url.as_bytes())));
+    if path.exists() {
+        return Ok(Some(path));
+    }
+
+    let image = ureq::get(url).call()?.body_mut().read_to_vec()?;
+    fs::create_dir_all(cache_dir())?;
+    // An interrupted download would otherwise be taken for the art from then on
+    state::write_atomic(&path, &image)?;
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_urls_are_decoded() {
+        let url = "file:///music/Beyonc%C3%A9/Cover%20Art.jpg";
+        let path = art(url).unwrap().unwrap();
+        assert_eq!(path, Path::new("/music/Beyoncé/Cover Art.jpg"));
+    }
+
+    #[test]
+    fn stray_percent_signs_are_kept() {
+        assert_eq!(percent_decode("/100%/a%2"), b"/100%/a%2");
+        assert_eq!(percent_decode("/%ff"), b"/\xff");
+    }
+}
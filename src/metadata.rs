@@ -0,0 +1,219 @@
+use chrono::{DateTime, Local};
+
+use crate::{geometry::Focus, screenshot::ImageFormat};
+
+pub struct Metadata {
+    fields: Vec<(&'static str, String)>,
+    captured: DateTime<Local>,
+}
+
+impl Metadata {
+    pub fn new(focus: &Focus, captured: DateTime<Local>) -> Self {
+        let fields = [
+            ("Title", focus.title.clone()),
+            ("AppId", focus.app_id.clone()),
+            ("Workspace", focus.workspace.clone()),
+            ("Creation Time", captured.to_rfc3339()),
+        ];
+        Metadata {
+            fields: fields
+                .into_iter()
+                .filter(|(_, value)| !value.is_empty())
+                .collect(),
+            captured,
+        }
+    }
+
+    fn field(&self, keyword: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == keyword)
+            .map(|(_, text)| text.as_str())
+    }
+}
+
+pub fn embed(bytes: &[u8], format: ImageFormat, metadata: &Metadata) -> Vec<u8> {
+    match format {
+        ImageFormat::Png => embed_png(bytes, metadata),
+        ImageFormat::Jpeg => embed_jpeg(bytes, metadata),
+        ImageFormat::Ppm => bytes.to_vec(),
+    }
+}
+
+// Text chunks go right after IHDR, which always spans the first 33 bytes
+fn embed_png(bytes: &[u8], metadata: &Metadata) -> Vec<u8> {
+    const IHDR_END: usize = 8 + 25;
+    let mut png = bytes[..IHDR_END].to_vec();
+    for (keyword, text) in &metadata.fields {
+        // tEXt is latin-1, iTXt holds utf-8 without compression
+        let mut chunk = b"iTXt".to_vec();
+        chunk.extend_from_slice(keyword.as_bytes());
+        chunk.extend_from_slice(&[0, 0, 0, 0, 0]);
+        chunk.extend_from_slice(text.as_bytes());
+        png.extend_from_slice(&(chunk.len() as u32 - 4).to_be_bytes());
+        png.extend_from_slice(&chunk);
+        png.extend_from_slice(&crc32fast::hash(&chunk).to_be_bytes());
+    }
+
+    png.extend_from_slice(&bytes[IHDR_END..]);
+    png
+}
+
+const ASCII: u16 = 2;
+const LONG: u16 = 4;
+const UNDEFINED: u16 = 7;
+
+// One big endian TIFF directory starting at `offset`, values that don't fit an entry follow it
+fn ifd(entries: &[(u16, u16, Vec<u8>)], offset: usize) -> Vec<u8> {
+    let mut data_offset = offset + 2 + entries.len() * 12 + 4;
+    let mut directory = (entries.len() as u16).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    for (tag, kind, value) in entries {
+        let count = match *kind {
+            LONG => value.len() / 4,
+            _ => value.len(),
+        };
+        directory.extend_from_slice(&tag.to_be_bytes());
+        directory.extend_from_slice(&kind.to_be_bytes());
+        directory.extend_from_slice(&(count as u32).to_be_bytes());
+        if value.len() <= 4 {
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            directory.extend_from_slice(&inline);
+            continue;
+        }
+
+        directory.extend_from_slice(&(data_offset as u32).to_be_bytes());
+        data.extend_from_slice(value);
+        // Offsets should stay on word boundaries
+        if value.len() % 2 == 1 {
+            data.push(0);
+        }
+        data_offset = offset + 2 + entries.len() * 12 + 4 + data.len();
+    }
+
+    directory.extend_from_slice(&[0, 0, 0, 0]);
+    directory.extend_from_slice(&data);
+    directory
+}
+
+fn ascii(text: &str) -> Vec<u8> {
+    let mut value = text.as_bytes().to_vec();
+    value.push(0);
+    value
+}
+
+// The title is the image description, the app_id and workspace go into the user comment, which
+// exiftool shows as is. Text is written as utf-8 even though EXIF asks for ascii.
+fn exif(metadata: &Metadata) -> Vec<u8> {
+    let date = ascii(&metadata.captured.format("%Y:%m:%d %H:%M:%S").to_string());
+    let comment: Vec<_> = ["AppId", "Workspace"]
+        .into_iter()
+        .filter_map(|keyword| Some(format!("{keyword}: {}", metadata.field(keyword)?)))
+        .collect();
+    let mut user_comment = b"ASCII\0\0\0".to_vec();
+    user_comment.extend_from_slice(comment.join(", ").as_bytes());
+
+    let exif_entries = [
+        (0x9003, ASCII, date.clone()),
+        (0x9286, UNDEFINED, user_comment),
+    ];
+    let mut entries = Vec::new();
+    if let Some(title) = metadata.field("Title") {
+        entries.push((0x010E, ASCII, ascii(title)));
+    }
+    entries.push((0x0132, ASCII, date));
+    // The exif directory follows the first one, whose size doesn't depend on the pointer value
+    entries.push((0x8769, LONG, vec![0; 4]));
+    let exif_offset = 8 + ifd(&entries, 8).len();
+    entries.last_mut().unwrap().2 = (exif_offset as u32).to_be_bytes().to_vec();
+
+    let mut tiff = b"Exif\0\0MM\0\x2A\0\0\0\x08".to_vec();
+    tiff.extend_from_slice(&ifd(&entries, 8));
+    tiff.extend_from_slice(&ifd(&exif_entries, exif_offset));
+    tiff
+}
+
+// An EXIF APP1 segment after SOI, or after the JFIF APP0 segment which has to come first
+fn embed_jpeg(bytes: &[u8], metadata: &Metadata) -> Vec<u8> {
+    let exif = exif(metadata);
+    // Segment lengths are 16 bit and include the length field itself
+    let Ok(length) = u16::try_from(exif.len() + 2) else {
+        return bytes.to_vec();
+    };
+
+    let insert_at = match bytes.get(2..6) {
+        Some([0xFF, 0xE0, high, low]) => 4 + u16::from_be_bytes([*high, *low]) as usize,
+        _ => 2,
+    }
+    .min(bytes.len());
+    let mut jpeg = bytes[..insert_at].to_vec();
+    jpeg.extend_from_slice(&[0xFF, 0xE1]);
+    jpeg.extend_from_slice(&length.to_be_bytes());
+    jpeg.extend_from_slice(&exif);
+    jpeg.extend_from_slice(&bytes[insert_at..]);
+    jpeg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Metadata {
+        let focus = Focus {
+            app_id: "foot".to_string(),
+            title: "vim notes.txt".to_string(),
+            output: String::new(),
+            workspace: "1".to_string(),
+        };
+        Metadata::new(&focus, Local::now())
+    }
+
+    fn u16_at(bytes: &[u8], index: usize) -> u16 {
+        u16::from_be_bytes([bytes[index], bytes[index + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], index: usize) -> u32 {
+        u32::from_be_bytes(bytes[index..index + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn exif_goes_after_jfif() {
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        jpeg.extend_from_slice(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        jpeg.extend_from_slice(&[0xFF, 0xDB]);
+        let embedded = embed_jpeg(&jpeg, &metadata());
+        assert_eq!(&embedded[..20], &jpeg[..20]);
+        assert_eq!(&embedded[20..22], &[0xFF, 0xE1]);
+        assert_eq!(&embedded[24..30], b"Exif\0\0");
+        assert!(embedded.ends_with(&[0xFF, 0xDB]));
+    }
+
+    #[test]
+    fn exif_goes_after_soi_without_jfif() {
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xDB];
+        let embedded = embed_jpeg(&jpeg, &metadata());
+        assert_eq!(&embedded[2..4], &[0xFF, 0xE1]);
+    }
+
+    #[test]
+    fn exif_directories_point_at_their_values() {
+        let tiff = &exif(&metadata())[6..];
+        assert_eq!(&tiff[..8], b"MM\0\x2A\0\0\0\x08");
+        assert_eq!(u16_at(tiff, 8), 3);
+        assert_eq!(u16_at(tiff, 10), 0x010E);
+        let title = u32_at(tiff, 10 + 8) as usize;
+        assert_eq!(&tiff[title..title + 14], b"vim notes.txt\0");
+
+        assert_eq!(u16_at(tiff, 10 + 24), 0x8769);
+        let exif_ifd = u32_at(tiff, 10 + 24 + 8) as usize;
+        assert_eq!(u16_at(tiff, exif_ifd), 2);
+        assert_eq!(u16_at(tiff, exif_ifd + 2 + 12), 0x9286);
+        let comment = u32_at(tiff, exif_ifd + 2 + 12 + 8) as usize;
+        let length = u32_at(tiff, exif_ifd + 2 + 12 + 4) as usize;
+        assert_eq!(
+            &tiff[comment..comment + length],
+            b"ASCII\0\0\0AppId: foot, Workspace: 1"
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use std::process::Command;
+
+use clap::Subcommand;
+use color_eyre::Result;
+
+use crate::{
+    command::{CommandFailed, run_command},
+    volume::wpctl_level,
+};
+
+const SOURCE: &str = "@DEFAULT_AUDIO_SOURCE@";
+
+#[derive(Subcommand)]
+pub enum MicAction {
+    Toggle,
+    Mute,
+    Unmute,
+}
+
+// For keyboards with a mute light, gets MIC_MUTED set to true or false
+fn led(command: &str, muted: bool) -> Result<()> {
+    let status = Command::new("sh")
+        .args(["-c", command])
+        .env("MIC_MUTED", muted.to_string())
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(CommandFailed::new(command, status).into()),
+    }
+}
+
+pub fn mic(action: MicAction, led_command: Option<&str>) -> Result<()> {
+    let mute = match action {
+        MicAction::Toggle => "toggle",
+        MicAction::Mute => "1",
+        MicAction::Unmute => "0",
+    };
+    run_command("wpctl", ["set-mute", SOURCE, mute])?;
+    let muted = wpctl_level(SOURCE)?.muted;
+    if let Some(command) = led_command {
+        led(command, muted)?;
+    }
+
+    let (summary, icon) = match muted {
+        true => ("Microphone muted", "microphone-sensitivity-muted"),
+        false => ("Microphone on", "microphone-sensitivity-high"),
+    };
+    let args = [
+        summary,
+        "-h",
+        "string:x-canonical-private-synchronous:mic",
+        "-i",
+        icon,
+        "-t",
+        "1500",
+    ];
+    run_command("notify-send", args)
+}
@@ -0,0 +1,1155 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fmt::Write as _,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Args, Subcommand, ValueEnum};
+use color_eyre::{
+    Result,
+    eyre::{OptionExt, eyre},
+};
+
+use crate::{
+    command::{self, run_command, run_command_with_stdio},
+    geometry::Cancelled,
+    notification, state,
+};
+
+mod bisect;
+mod cache;
+mod closure;
+mod deploy;
+mod generations;
+mod hooks;
+mod lock;
+mod option;
+mod progress;
+mod search;
+mod secrets;
+
+use cache::{CacheAction, CacheTool};
+use generations::GenerationsAction;
+use hooks::{Hook, Hooks};
+use secrets::SecretsAction;
+
+#[derive(Args)]
+pub struct NixosOptions {
+    #[arg(long, env = "NH_FLAKE")]
+    flake: PathBuf,
+    // Kernel and driver updates are better staged for the next boot
+    #[arg(long, value_enum, default_value = "switch")]
+    activation: Activation,
+    // Other hosts are deployed to over ssh under their configuration name
+    #[arg(long = "device")]
+    devices: Vec<String>,
+    #[arg(long, conflicts_with = "devices")]
+    all_hosts: bool,
+    // Editing from a stale checkout means a merge later on
+    #[arg(long, overrides_with = "no_pull")]
+    pull: bool,
+    #[arg(long, overrides_with = "pull")]
+    no_pull: bool,
+    // Changes already there before the editor opens or an update starts, build and vm use them
+    #[arg(long, value_enum, default_value = "prompt")]
+    on_dirty: OnDirty,
+    // Evaluation errors show up in seconds instead of after a long build
+    #[arg(long)]
+    check: bool,
+    #[arg(long, requires = "check")]
+    dry_build: bool,
+    // Skips confirming the edited changes, for keybinds
+    #[arg(long)]
+    yes: bool,
+    #[arg(long, conflicts_with = "yes")]
+    show_diff: bool,
+    // Pushed to each in turn, the first one is pulled from
+    #[arg(long = "remote")]
+    remotes: Vec<String>,
+    #[arg(long)]
+    branch: Option<String>,
+    #[arg(long)]
+    no_push: bool,
+    // Only network failures are retried, with exponential backoff
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+    // Rotated keys must reach every secret before the change is committed
+    #[arg(long)]
+    rekey: bool,
+    // For servers nobody is watching notifications on
+    #[arg(long)]
+    auto_reboot: bool,
+    #[arg(long)]
+    no_fmt: bool,
+    // statix and deadnix on top of nix fmt
+    #[arg(long)]
+    lint: bool,
+    // nh's and nix's own output instead of the compact progress line
+    #[arg(long)]
+    plain: bool,
+    // Other machines substitute from it instead of rebuilding
+    #[arg(long, env = "NIXOS_CACHE")]
+    cache: Option<String>,
+    #[arg(long, value_enum, default_value = "cachix")]
+    cache_tool: CacheTool,
+    // Activates a specialisation of the configuration instead of its base
+    #[arg(long)]
+    specialisation: Option<String>,
+    #[command(flatten)]
+    hooks: Hooks,
+    // Configurations of several devices are built at once before activating them one by one
+    #[arg(long, default_value_t = 4)]
+    jobs: usize,
+}
+
+impl NixosOptions {
+    // Without either option git's own upstream config decides
+    fn remotes(&self) -> Option<Vec<&str>> {
+        match (&self.remotes[..], &self.branch) {
+            ([], None) => None,
+            ([], Some(_)) => Some(vec!["origin"]),
+            (remotes, _) => Some(remotes.iter().map(String::as_str).collect()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OnDirty {
+    Prompt,
+    Stash,
+    Abort,
+    Include,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Activation {
+    Switch,
+    Boot,
+    Test,
+}
+
+impl Activation {
+    fn name(self) -> &'static str {
+        match self {
+            Activation::Switch => "switch",
+            Activation::Boot => "boot",
+            Activation::Test => "test",
+        }
+    }
+
+    // test only activates, boot only updates the profile
+    fn system(self) -> &'static Path {
+        match self {
+            Activation::Test => Path::new("/run/current-system"),
+            Activation::Switch | Activation::Boot => Path::new("/nix/var/nix/profiles/system"),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum HomeAction {
+    Configure {
+        #[arg(long, env = "EDITOR")]
+        editor_name: String,
+        #[arg(long)]
+        update: bool,
+        #[arg(long)]
+        edit: bool,
+    },
+    Update,
+}
+
+#[derive(Subcommand)]
+pub enum NixosAction {
+    Configure {
+        #[arg(long, env = "EDITOR")]
+        editor_name: String,
+        #[arg(long)]
+        update: bool,
+        // Opens the commit editor with the generated message
+        #[arg(long)]
+        edit: bool,
+        // Opens a single .nix file chosen with the picker instead of the flake root
+        #[arg(long)]
+        pick: bool,
+        #[arg(long, env = "NIXOS_PICKER", default_value = "fuzzel --dmenu")]
+        picker: String,
+    },
+    Update,
+    Generations {
+        #[command(subcommand)]
+        action: GenerationsAction,
+    },
+    // Builds without switching, committing or pushing and shows what would change
+    Build {
+        #[arg(long)]
+        update: bool,
+    },
+    Secrets {
+        #[command(subcommand)]
+        action: SecretsAction,
+    },
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    // Explains why the closure of the running system contains a package
+    Why {
+        package: String,
+    },
+    Size {
+        // The result of the last nixos build instead of the running system
+        #[arg(long)]
+        built: bool,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        // Compares against the previous generation
+        #[arg(long)]
+        diff: bool,
+    },
+    // Shows nixpkgs packages, or adds the picked one to a package list in the flake
+    Search {
+        query: String,
+        #[arg(long)]
+        add: Option<PathBuf>,
+        #[arg(long, env = "NIXOS_PICKER", default_value = "fuzzel --dmenu")]
+        picker: String,
+    },
+    // The value, default and description of an option in the device's configuration
+    Option {
+        path: String,
+    },
+    // nix repl with the flake loaded and the device's configuration bound to host, config and pkgs
+    Repl,
+    Specialisations,
+    // Finds the input revision that broke the configuration, asking after each build
+    Bisect {
+        #[arg(long)]
+        input: String,
+        #[arg(long)]
+        good: String,
+        #[arg(long)]
+        bad: String,
+        // Activates each candidate with test instead of only building it
+        #[arg(long)]
+        test: bool,
+    },
+    // How far each input's lock is behind its upstream branch
+    Outdated {
+        // Only notifies about inputs further behind than this many days, for timers
+        #[arg(long)]
+        notify_after: Option<i64>,
+    },
+    Vm {
+        #[arg(long)]
+        host: Option<String>,
+        #[arg(long)]
+        run: bool,
+    },
+    Deploy {
+        configuration: String,
+        #[arg(long)]
+        target_host: String,
+        #[arg(long, conflicts_with = "build_local")]
+        build_remote: bool,
+        // The default, only there to be explicit
+        #[arg(long)]
+        build_local: bool,
+    },
+    // Standalone home-manager from the same flake
+    Home {
+        #[command(subcommand)]
+        action: HomeAction,
+    },
+    // Removes boot menu entries of generations that were garbage collected
+    BootClean {
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Clean {
+        #[arg(long, default_value_t = 5)]
+        keep: u32,
+        #[arg(long, default_value = "7d")]
+        keep_since: String,
+    },
+}
+
+pub fn nixos(action: NixosAction, options: NixosOptions) -> Result<()> {
+    match action {
+        NixosAction::Configure {
+            editor_name,
+            update,
+            edit,
+            pick,
+            picker,
+        } => {
+            let file = match pick {
+                true => Some(pick_file(&picker, &options.flake)?),
+                false => None,
+            };
+            nixos_configure(editor_name, file, update, edit, &options)
+        }
+        NixosAction::Update => nixos_update(&options),
+        NixosAction::Generations { action } => generations::generations_action(action),
+        NixosAction::Build { update } => nixos_build(update, &options),
+        NixosAction::Deploy {
+            configuration,
+            target_host,
+            build_remote,
+            ..
+        } => {
+            env::set_current_dir(&options.flake)?;
+            deploy::deploy(
+                &configuration,
+                &target_host,
+                build_remote,
+                options.activation,
+                options.specialisation.as_deref(),
+            )
+        }
+        NixosAction::Home { action } => home(action, &options),
+        NixosAction::Cache { action } => {
+            cache::cache_action(action, options.cache_tool, options.cache.as_deref())
+        }
+        NixosAction::Why { package } => closure::why(&package),
+        NixosAction::Size { built, limit, diff } => {
+            let system = match built {
+                true => state::runtime_path("nixos-build"),
+                false => PathBuf::from("/run/current-system"),
+            };
+            let previous = match diff {
+                true => Some(generations::previous()?),
+                false => None,
+            };
+            closure::size(&system, limit, previous.as_deref())
+        }
+        NixosAction::Search { query, add, picker } => {
+            nixos_search(&query, add.as_deref(), &picker, &options)
+        }
+        NixosAction::Option { path } => {
+            env::set_current_dir(&options.flake)?;
+            option::option(&host(&options)?, &path)
+        }
+        NixosAction::Repl => nixos_repl(&host(&options)?, &options.flake),
+        NixosAction::Specialisations => specialisations(&options),
+        NixosAction::Bisect {
+            input,
+            good,
+            bad,
+            test,
+        } => {
+            env::set_current_dir(&options.flake)?;
+            bisect::bisect(&input, &good, &bad, test)
+        }
+        NixosAction::Outdated { notify_after } => {
+            let lock = fs::read_to_string(options.flake.join("flake.lock"))?;
+            lock::outdated(&lock, notify_after)
+        }
+        NixosAction::Vm { host, run } => nixos_vm(host, run, &options.flake),
+        NixosAction::Secrets { action } => {
+            env::set_current_dir(&options.flake)?;
+            secrets::secrets_action(action)
+        }
+        NixosAction::BootClean { dry_run } => generations::boot_clean(dry_run),
+        NixosAction::Clean { keep, keep_since } => nixos_clean(keep, &keep_since),
+    }
+}
+
+fn nixos_configure(
+    editor_name: String,
+    file: Option<String>,
+    update: bool,
+    edit: bool,
+    options: &NixosOptions,
+) -> Result<()> {
+    env::set_current_dir(&options.flake)?;
+    let hosts = with_dirty_set_aside(options.on_dirty, || {
+        pull(options)?;
+        options.hooks.run(Hook::PreEdit, None, None)?;
+        run_command(&editor_name, file.as_deref())?;
+        format_flake(options)?;
+        confirm_changes(options)?;
+        if options.rekey {
+            secrets::rekey()?;
+        }
+
+        run_command("git", ["add", "."])?;
+        let hosts = hosts(options)?;
+        check(options, &hosts)?;
+        let old_lock = fs::read_to_string("flake.lock").unwrap_or_default();
+        activate(update, &hosts, options)?;
+        if update {
+            notify_updated_inputs(&old_lock)?;
+        }
+
+        let mut subject = hosts.join(", ");
+        // test doesn't add a generation, and only ours can be looked up
+        if !matches!(options.activation, Activation::Test) && hosts.contains(&hostname()?) {
+            write!(subject, ": generation {}", generations::latest()?)?;
+        }
+
+        commit_and_push(subject, edit, options)?;
+        Ok(hosts)
+    })?;
+    if hosts.contains(&hostname()?) {
+        reboot_if_needed(options)?;
+    }
+
+    Ok(())
+}
+
+fn pick_file(picker: &str, flake: &Path) -> Result<String> {
+    env::set_current_dir(flake)?;
+    let files = git_output(["ls-files", "*.nix"])?;
+    command::pick(picker, &files)
+}
+
+fn nixos_search(
+    query: &str,
+    add: Option<&Path>,
+    picker: &str,
+    options: &NixosOptions,
+) -> Result<()> {
+    let results = search::search(query)?;
+    if results.is_empty() {
+        return Err(eyre!("No packages match {query}"));
+    }
+
+    let listing = search::format(&results);
+    let Some(file) = add else {
+        print!("{listing}");
+        return Ok(());
+    };
+
+    let picked = command::pick(picker, &listing)?;
+    let attribute = picked.split_whitespace().next().ok_or(Cancelled)?;
+    env::set_current_dir(&options.flake)?;
+    search::add_package(file, attribute, &host(options)?)?;
+    println!("Added {attribute} to {}", file.display());
+    Ok(())
+}
+
+fn ask(question: &str) -> Result<String> {
+    print!("{question}");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    // Ctrl-D or a closed stdin would otherwise read as an empty answer forever
+    if io::stdin().read_line(&mut answer)? == 0 {
+        return Err(Cancelled.into());
+    }
+
+    Ok(answer.trim().to_string())
+}
+
+const STASH_MESSAGE: &str = "scripts: set aside by nixos";
+
+// Returns the stash commit when the changes were stashed
+fn set_aside_dirty(on_dirty: OnDirty) -> Result<Option<String>> {
+    let status = git_output(["status", "--porcelain"])?;
+    if status.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let on_dirty = match on_dirty {
+        // Without a terminal nobody can answer
+        OnDirty::Prompt if !io::stdin().is_terminal() => OnDirty::Abort,
+        OnDirty::Prompt => {
+            let question = format!(
+                "The flake has uncommitted changes:\n{status}[i]nclude, [s]tash or [a]bort? "
+            );
+            match ask(&question)?.as_str() {
+                "i" | "include" => OnDirty::Include,
+                "s" | "stash" => OnDirty::Stash,
+                _ => OnDirty::Abort,
+            }
+        }
+        on_dirty => on_dirty,
+    };
+
+    match on_dirty {
+        OnDirty::Include => Ok(None),
+        OnDirty::Stash => {
+            let args = ["stash", "push", "--include-untracked", "-m", STASH_MESSAGE];
+            run_command("git", args)?;
+            Ok(Some(git_output(["rev-parse", "--short", "stash@{0}"])?))
+        }
+        OnDirty::Abort | OnDirty::Prompt => Err(eyre!(
+            "The flake has uncommitted changes:\n{status}Commit or stash them first"
+        )),
+    }
+}
+
+fn format_flake(options: &NixosOptions) -> Result<()> {
+    if !options.no_fmt {
+        run_command("nix", ["fmt"])?;
+    }
+
+    if options.lint {
+        run_command("statix", ["check", "."])?;
+        run_command("deadnix", ["--fail", "."])?;
+    }
+
+    Ok(())
+}
+
+// Every early return in between still brings the stashed changes back, or says where they are
+fn with_dirty_set_aside<T>(on_dirty: OnDirty, body: impl FnOnce() -> Result<T>) -> Result<T> {
+    let stash = set_aside_dirty(on_dirty)?;
+    let result = body();
+    let Some(stash) = stash else {
+        return result;
+    };
+
+    if let Err(error) = run_command("git", ["stash", "pop"]) {
+        eprintln!(
+            "Your uncommitted changes are still stashed as {stash} (\"{STASH_MESSAGE}\"), \
+             bring them back with git stash apply {stash}"
+        );
+        return result.and(Err(error));
+    }
+
+    result
+}
+
+// An editor closed by accident must not deploy a half finished config
+fn confirm_changes(options: &NixosOptions) -> Result<()> {
+    if options.yes {
+        return Ok(());
+    }
+
+    run_command("git", ["diff", "HEAD", "--stat"])?;
+    let untracked = git_output(["ls-files", "--others", "--exclude-standard"])?;
+    for file in untracked.lines() {
+        println!(" {file} (new)");
+    }
+
+    if options.show_diff {
+        run_command("git", ["diff", "HEAD"])?;
+    }
+
+    let confirmed = io::stdin().is_terminal()
+        && matches!(
+            ask("Switch to these changes? [y/N] ")?.as_str(),
+            "y" | "Y" | "yes"
+        );
+    if !confirmed {
+        return Err(eyre!("Changes not confirmed, nothing was switched"));
+    }
+
+    Ok(())
+}
+
+fn pull(options: &NixosOptions) -> Result<()> {
+    if !options.pull || options.no_pull {
+        return Ok(());
+    }
+
+    let remote = options.remotes().map(|remotes| remotes[0]);
+    let args = ["pull", "--rebase", "--autostash"]
+        .into_iter()
+        .chain(remote)
+        .chain(options.branch.as_deref());
+    let args: Vec<_> = args.collect();
+    let Err(error) = command::run_with_retries("git", &args, options.retries) else {
+        return Ok(());
+    };
+
+    let conflicts = git_output(["diff", "--name-only", "--diff-filter=U"])?;
+    if conflicts.trim().is_empty() {
+        return Err(error);
+    }
+
+    // Aborting also restores the autostash, leaving the checkout as it was
+    run_command("git", ["rebase", "--abort"])?;
+    Err(eyre!(
+        "Pulling conflicts with local commits in:\n{}Resolve them by hand before configuring",
+        conflicts
+    ))
+}
+
+fn commit_and_push(subject: String, edit: bool, options: &NixosOptions) -> Result<()> {
+    let Some(message) = commit_message(subject)? else {
+        println!("Nothing to commit");
+        return Ok(());
+    };
+
+    let args = ["commit", "-a", "-m", &message]
+        .into_iter()
+        .chain(edit.then_some("--edit"));
+    run_command("git", args)?;
+    if options.no_push {
+        return Ok(());
+    }
+
+    match options.remotes() {
+        None => command::run_with_retries("git", &["push"], options.retries)?,
+        Some(remotes) => {
+            let refspec = match &options.branch {
+                Some(branch) => format!("HEAD:{branch}"),
+                None => "HEAD".to_string(),
+            };
+            for remote in remotes {
+                command::run_with_retries("git", &["push", remote, &refspec], options.retries)?;
+            }
+        }
+    }
+
+    options.hooks.run(Hook::PostPush, None, None)
+}
+
+fn git_output<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<String> {
+    Ok(String::from_utf8(run_command_with_stdio(
+        "git", args, true, None,
+    )?)?)
+}
+
+// Keybind triggered rebuilds have no terminal for the commit editor
+fn commit_message(mut message: String) -> Result<Option<String>> {
+    let files = git_output(["diff", "HEAD", "--name-only"])?;
+    if files.trim().is_empty() {
+        return Ok(None);
+    }
+
+    message.push_str("\n\nChanged files:\n");
+    for file in files.lines() {
+        writeln!(message, "- {file}")?;
+    }
+
+    let old_lock = git_output(["show", "HEAD:flake.lock"]).unwrap_or_default();
+    let new_lock = fs::read_to_string("flake.lock").unwrap_or_default();
+    let changes = lock::diff(&old_lock, &new_lock)?;
+    if !changes.is_empty() {
+        message.push_str("\nUpdated inputs:\n");
+    }
+
+    for change in changes {
+        writeln!(message, "- {change}")?;
+    }
+
+    Ok(Some(message))
+}
+
+fn nixos_update(options: &NixosOptions) -> Result<()> {
+    env::set_current_dir(&options.flake)?;
+    let hosts = with_dirty_set_aside(options.on_dirty, || {
+        run_command("git", ["add", "."])?;
+        let hosts = hosts(options)?;
+        check(options, &hosts)?;
+        let old_lock = fs::read_to_string("flake.lock").unwrap_or_default();
+        activate(true, &hosts, options)?;
+        notify_updated_inputs(&old_lock)?;
+        Ok(hosts)
+    })?;
+    if hosts.contains(&hostname()?) {
+        reboot_if_needed(options)?;
+    }
+
+    Ok(())
+}
+
+// A live switch keeps running the booted kernel, so new ones only apply after a reboot
+fn reboot_if_needed(options: &NixosOptions) -> Result<()> {
+    let booted = Path::new("/run/booted-system");
+    let new = options.activation.system();
+    let mut changed = Vec::new();
+    for part in ["kernel", "initrd", "kernel-modules"] {
+        if fs::canonicalize(booted.join(part)).ok() != fs::canonicalize(new.join(part)).ok() {
+            changed.push(part);
+        }
+    }
+
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    if options.auto_reboot {
+        return run_command("systemctl", ["reboot"]);
+    }
+
+    let message = format!("The {} changed since booting", changed.join(", "));
+    println!("{message}");
+    let action = notification::notify_with_actions(
+        "NixOS",
+        &message,
+        "system-reboot",
+        10000,
+        &[("reboot", "Reboot now")],
+    )?;
+    match action.as_deref() {
+        Some("reboot") => run_command("systemctl", ["reboot"]),
+        _ => Ok(()),
+    }
+}
+
+fn notify_updated_inputs(old_lock: &str) -> Result<()> {
+    let new_lock = fs::read_to_string("flake.lock").unwrap_or_default();
+    let changes = lock::diff(old_lock, &new_lock)?;
+    let message = match changes.is_empty() {
+        true => "All inputs were up to date".to_string(),
+        false => changes
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    println!("{message}");
+    run_command("notify-send", ["Flake inputs", &message, "-t", "6000"])
+}
+
+fn check(options: &NixosOptions, hosts: &[String]) -> Result<()> {
+    if !options.check {
+        return Ok(());
+    }
+
+    run_command("nix", ["flake", "check"])?;
+    if options.dry_build {
+        for host in hosts {
+            run_command(
+                "nixos-rebuild",
+                ["dry-build", "--flake", &format!(".#{host}")],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn update_inputs(retries: u32) -> Result<()> {
+    command::run_with_retries("nix", &["flake", "update"], retries)
+}
+
+fn home_switch(update: bool, retries: u32, plain: bool) -> Result<()> {
+    if update {
+        update_inputs(retries)?;
+    }
+
+    let attempts = (retries + 1).to_string();
+    let args = ["home", "switch", ".", "--", "--option", "download-attempts"];
+    nh(args.into_iter().chain([attempts.as_str()]), plain)?;
+    run_command("notify-send", ["Home Manager", "Switched", "-t", "6000"])
+}
+
+fn home(action: HomeAction, options: &NixosOptions) -> Result<()> {
+    env::set_current_dir(&options.flake)?;
+    match action {
+        HomeAction::Configure {
+            editor_name,
+            update,
+            edit,
+        } => with_dirty_set_aside(options.on_dirty, || {
+            pull(options)?;
+            options.hooks.run(Hook::PreEdit, None, None)?;
+            run_command(&editor_name, None)?;
+            format_flake(options)?;
+            confirm_changes(options)?;
+            if options.rekey {
+                secrets::rekey()?;
+            }
+
+            run_command("git", ["add", "."])?;
+            check(options, &[])?;
+            let old_lock = fs::read_to_string("flake.lock").unwrap_or_default();
+            home_switch(update, options.retries, options.plain)?;
+            if update {
+                notify_updated_inputs(&old_lock)?;
+            }
+
+            let user = env::var("USER")?;
+            let subject = format!("{user}@{}: home-manager", hostname()?);
+            commit_and_push(subject, edit, options)
+        }),
+        HomeAction::Update => with_dirty_set_aside(options.on_dirty, || {
+            run_command("git", ["add", "."])?;
+            check(options, &[])?;
+            let old_lock = fs::read_to_string("flake.lock").unwrap_or_default();
+            home_switch(true, options.retries, options.plain)?;
+            notify_updated_inputs(&old_lock)
+        }),
+    }
+}
+
+fn hostname() -> Result<String> {
+    Ok(fs::read_to_string("/proc/sys/kernel/hostname")?
+        .trim()
+        .to_string())
+}
+
+// Commands that look at a single configuration use the first device
+fn host(options: &NixosOptions) -> Result<String> {
+    match options.devices.first() {
+        Some(device) => Ok(device.clone()),
+        None => hostname(),
+    }
+}
+
+fn hosts(options: &NixosOptions) -> Result<Vec<String>> {
+    if options.all_hosts {
+        let args = [
+            "eval",
+            ".#nixosConfigurations",
+            "--apply",
+            "builtins.attrNames",
+            "--json",
+        ];
+        let hosts = run_command_with_stdio("nix", args, true, None)?;
+        return Ok(serde_json::from_slice(&hosts)?);
+    }
+
+    match options.devices.is_empty() {
+        true => Ok(vec![hostname()?]),
+        false => Ok(options.devices.clone()),
+    }
+}
+
+// The compact progress needs nix's json log, which nom would otherwise consume
+fn nh<'a>(args: impl IntoIterator<Item = &'a str>, plain: bool) -> Result<()> {
+    let mut args: Vec<_> = args.into_iter().collect();
+    if plain || !io::stderr().is_terminal() {
+        return run_command("nh", args);
+    }
+
+    // Everything after -- is passed on to nix
+    match args.iter().position(|arg| *arg == "--") {
+        Some(separator) => args.insert(separator, "--no-nom"),
+        None => args.extend(["--no-nom", "--"]),
+    }
+
+    args.extend(["--log-format", "internal-json"]);
+    progress::run("nh", &args)
+}
+
+fn activate_local<'a>(
+    args: impl IntoIterator<Item = &'a str>,
+    options: &NixosOptions,
+) -> Result<String> {
+    let activation = options.activation;
+    let before = fs::canonicalize(activation.system())?;
+    nh(args, options.plain)?;
+    let after = fs::canonicalize(activation.system())?;
+    if before == after {
+        return Ok("System unchanged".to_string());
+    }
+
+    if let Some(name) = &options.cache {
+        cache::push(options.cache_tool, name, &after)?;
+    }
+
+    let diff = generations::diff_closures(&before, &after)?;
+    print!("{diff}");
+    Ok(generations::summary(&diff))
+}
+
+struct HostBuild {
+    system: Result<String>,
+    elapsed: Duration,
+    size_delta: Option<i128>,
+}
+
+fn running_system(host: &str, remote: bool) -> Result<String> {
+    match remote {
+        true => {
+            let args = [host, "readlink", "-f", "/run/current-system"];
+            Ok(
+                String::from_utf8(run_command_with_stdio("ssh", args, true, None)?)?
+                    .trim()
+                    .to_string(),
+            )
+        }
+        false => Ok(fs::canonicalize("/run/current-system")?
+            .to_string_lossy()
+            .into_owned()),
+    }
+}
+
+// Compared to what the device runs now, a failed lookup only loses that column
+fn size_delta(host: &str, remote: bool, system: &str) -> Option<i128> {
+    let store = remote.then(|| format!("ssh://{host}"));
+    let running = running_system(host, remote).ok()?;
+    let old = closure::closure_size(&running, store.as_deref()).ok()?;
+    let new = closure::closure_size(system, None).ok()?;
+    Some(i128::from(new) - i128::from(old))
+}
+
+// Activating afterwards finds everything already built
+fn build_hosts(hosts: &[String], local: &str, options: &NixosOptions) -> Vec<HostBuild> {
+    command::run_parallel(hosts, options.jobs, |host| {
+        let toplevel = format!(".#nixosConfigurations.{host}.config.system.build.toplevel");
+        let args = ["build", &toplevel, "--no-link", "--print-out-paths"];
+        let (system, elapsed) = command::timed(|| {
+            let output = run_command_with_stdio("nix", args, true, None)?;
+            Ok(String::from_utf8(output)?.trim().to_string())
+        });
+        let size_delta = system
+            .as_ref()
+            .ok()
+            .and_then(|system| size_delta(host, host != local, system));
+        HostBuild {
+            system,
+            elapsed,
+            size_delta,
+        }
+    })
+}
+
+fn print_host_table(rows: &[(&String, Option<&HostBuild>, String)]) {
+    println!("{:<16}  {:>8}  {:>10}  RESULT", "HOST", "BUILD", "SIZE");
+    for (host, build, result) in rows {
+        let elapsed = build.map_or(String::new(), |build| {
+            command::format_duration(build.elapsed)
+        });
+        let size = build
+            .and_then(|build| build.size_delta)
+            .map_or(String::new(), closure::human);
+        println!("{host:<16}  {elapsed:>8}  {size:>10}  {result}");
+    }
+}
+
+// Hosts are deployed one after another, a failure doesn't stop the rest
+fn activate(update: bool, hosts: &[String], options: &NixosOptions) -> Result<()> {
+    let activation = options.activation;
+    // Updated separately from nh, so that only the update is retried
+    if update {
+        update_inputs(options.retries)?;
+    }
+
+    // Substitutions are retried by nix itself
+    let attempts = (options.retries + 1).to_string();
+    let local = hostname()?;
+    let builds = match hosts {
+        [_] => Vec::new(),
+        _ => build_hosts(hosts, &local, options),
+    };
+    let mut summaries = Vec::new();
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (index, host) in hosts.iter().enumerate() {
+        let build = builds.get(index);
+        if let Some(HostBuild {
+            system: Err(error), ..
+        }) = build
+        {
+            summaries.push(format!("{host}: Build failed: {error}"));
+            rows.push((host, build, "Build failed".to_string()));
+            errors.push(eyre!("{error}"));
+            continue;
+        }
+
+        let remote = *host != local;
+        let args = ["os", activation.name(), ".", "--hostname", host]
+            .into_iter()
+            .chain(
+                remote
+                    .then_some(["--target-host", host])
+                    .into_iter()
+                    .flatten(),
+            )
+            .chain(
+                options
+                    .specialisation
+                    .iter()
+                    .flat_map(|name| ["--specialisation", name]),
+            )
+            .chain(["--", "--option", "download-attempts", &attempts]);
+        let (result, elapsed) = command::timed(|| {
+            options.hooks.run(Hook::PreSwitch, Some(host), None)?;
+            match remote {
+                true => nh(args, options.plain).map(|_| "Deployed".to_string()),
+                false => activate_local(args, options),
+            }
+        });
+        let elapsed = command::format_duration(elapsed);
+        // test doesn't add a generation, and a failed lookup only leaves it out of the summary
+        let generation = match result {
+            Ok(_) if !remote && !matches!(activation, Activation::Test) => {
+                generations::latest().ok()
+            }
+            _ => None,
+        };
+        let result = result.and_then(|summary| {
+            options
+                .hooks
+                .run(Hook::PostSwitch, Some(host), generation)?;
+            Ok(summary)
+        });
+        let summary = match (result, generation) {
+            (Ok(summary), Some(generation)) => {
+                format!("{summary}\nGeneration {generation}, took {elapsed}")
+            }
+            (Ok(summary), None) => format!("{summary}\nTook {elapsed}"),
+            (Err(error), _) => {
+                let summary = format!("Failed after {elapsed}: {error}");
+                errors.push(error);
+                summary
+            }
+        };
+        rows.push((
+            host,
+            build,
+            summary.lines().next().unwrap_or_default().to_string(),
+        ));
+        summaries.push(match hosts {
+            [_] => summary,
+            _ => format!("{host}: {summary}"),
+        });
+    }
+
+    let summary = summaries.join("\n");
+    if hosts.len() > 1 {
+        print_host_table(&rows);
+    }
+
+    // Rebuilds are long enough to have switched away from the terminal
+    let urgency = match errors.is_empty() {
+        true => "normal",
+        false => "critical",
+    };
+    run_command(
+        "notify-send",
+        ["NixOS", &summary, "-t", "6000", "-u", urgency],
+    )?;
+    match (errors.pop(), hosts) {
+        (None, _) => Ok(()),
+        (Some(error), [_]) => Err(error),
+        (Some(_), _) => Err(eyre!("Deployment failed on {} hosts", errors.len() + 1)),
+    }
+}
+
+fn nixos_build(update: bool, options: &NixosOptions) -> Result<()> {
+    env::set_current_dir(&options.flake)?;
+    // Kept out of the flake so that it doesn't end up in git
+    let out_link = state::runtime_path("nixos-build");
+    // Building exists to try out uncommitted edits, so the dirty tree is what gets built
+    if update {
+        update_inputs(options.retries)?;
+    }
+
+    run_command("git", ["add", "."])?;
+    let attempts = (options.retries + 1).to_string();
+    let args = [
+        "os",
+        "build",
+        ".",
+        "--out-link",
+        out_link.to_str().unwrap(),
+        "--",
+        "--option",
+        "download-attempts",
+        &attempts,
+    ];
+    nh(args, options.plain)?;
+    print!(
+        "{}",
+        generations::diff_closures(Path::new("/run/current-system"), &out_link)?
+    );
+    Ok(())
+}
+
+fn specialisations(options: &NixosOptions) -> Result<()> {
+    env::set_current_dir(&options.flake)?;
+    let attribute = format!(
+        ".#nixosConfigurations.{}.config.specialisation",
+        host(options)?
+    );
+    let args = [
+        "eval",
+        &attribute,
+        "--apply",
+        "builtins.attrNames",
+        "--json",
+    ];
+    let names: Vec<String> =
+        serde_json::from_slice(&run_command_with_stdio("nix", args, true, None)?)?;
+    for name in names {
+        println!("{name}");
+    }
+
+    Ok(())
+}
+
+fn nixos_repl(host: &str, flake: &Path) -> Result<()> {
+    // getFlake only takes absolute paths
+    let flake = fs::canonicalize(flake)?;
+    let expression = format!(
+        "let
+          flake = builtins.getFlake \"{}\";
+          host = flake.nixosConfigurations.{host};
+        in {{
+          inherit flake host;
+          inherit (host) config options pkgs;
+          lib = host.pkgs.lib;
+        }}",
+        flake.display()
+    );
+    run_command("nix", ["repl", "--expr", &expression])
+}
+
+// Risky changes like the display manager or kernel params are safer to try here first
+fn nixos_vm(host: Option<String>, run: bool, flake: &Path) -> Result<()> {
+    env::set_current_dir(flake)?;
+    let host = match host {
+        Some(host) => host,
+        None => hostname()?,
+    };
+    let out_link = state::runtime_path(&format!("nixos-vm-{host}"));
+    let installable = format!(".#nixosConfigurations.{host}.config.system.build.vm");
+    let args = [
+        "build",
+        &installable,
+        "--out-link",
+        out_link.to_str().unwrap(),
+    ];
+    run_command("git", ["add", "."])?;
+    run_command("nix", args)?;
+    // Named after networking.hostName, which can differ from the configuration name
+    let script = fs::read_dir(out_link.join("bin"))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|name| name.starts_with("run-") && name.ends_with("-vm"))
+        })
+        .ok_or_eyre("The vm has no run script")?;
+    let script = script.to_str().unwrap();
+    match run {
+        true => run_command(script, None),
+        false => {
+            println!("{script}");
+            Ok(())
+        }
+    }
+}
+
+fn store_available() -> Result<u64> {
+    let output = run_command_with_stdio(
+        "df",
+        ["--output=avail", "--block-size=1", "/nix/store"],
+        true,
+        None,
+    )?;
+    let output = String::from_utf8(output)?;
+    let available = output.lines().nth(1).ok_or_eyre("Unexpected df output")?;
+    Ok(available.trim().parse()?)
+}
+
+// Free space is compared rather than parsed from nix, which reports gc and optimise differently
+fn nixos_clean(keep: u32, keep_since: &str) -> Result<()> {
+    let before = store_available()?;
+    let keep = keep.to_string();
+    run_command(
+        "nh",
+        ["clean", "all", "--keep", &keep, "--keep-since", keep_since],
+    )?;
+    run_command("nix", ["store", "optimise"])?;
+    let reclaimed = store_available()?.saturating_sub(before);
+    let message = format!(
+        "Reclaimed {:.2} GiB",
+        reclaimed as f64 / (1u64 << 30) as f64
+    );
+    println!("{message}");
+    run_command("notify-send", ["NixOS", &message, "-t", "6000"])
+}
@@ -0,0 +1,147 @@
+use std::{fs, sync::OnceLock};
+
+use color_eyre::{
+    Result,
+    eyre::{OptionExt, eyre},
+};
+use serde_json::Value;
+
+use super::{ask, lock};
+use crate::command::run_command;
+
+// Oldest first, the compare api pages through at most 100 commits per page
+fn commits_between(owner: &str, repo: &str, good: &str, bad: &str) -> Result<Vec<String>> {
+    let mut commits = Vec::new();
+    for page in 1.. {
+        let path = format!("repos/{owner}/{repo}/compare/{good}...{bad}?per_page=100&page={page}");
+        let comparison = lock::github_api(&path)?;
+        let page = comparison["commits"]
+            .as_array()
+            .ok_or_eyre("Unexpected compare response")?;
+        if page.is_empty() {
+            break;
+        }
+
+        commits.extend(
+            page.iter()
+                .filter_map(|commit| Some(commit["sha"].as_str()?.to_string())),
+        );
+        if commits.len() >= comparison["total_commits"].as_u64().unwrap_or_default() as usize {
+            break;
+        }
+    }
+
+    Ok(commits)
+}
+
+fn try_revision(input: &str, owner: &str, repo: &str, revision: &str, test: bool) -> Result<()> {
+    let flake_ref = format!("github:{owner}/{repo}/{revision}");
+    run_command(
+        "nix",
+        ["flake", "lock", "--override-input", input, &flake_ref],
+    )?;
+    let action = match test {
+        true => "test",
+        false => "build",
+    };
+    run_command("nh", ["os", action, "."])
+}
+
+// A failed build is still worth judging, breakage often shows up as one
+fn judge(revision: &str) -> Result<Option<bool>> {
+    loop {
+        let question = format!("Is {} [g]ood, [b]ad or [s]kipped? ", &revision[..12]);
+        match ask(&question)?.as_str() {
+            "g" | "good" => return Ok(Some(true)),
+            "b" | "bad" => return Ok(Some(false)),
+            "s" | "skip" => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+fn search(input: &str, owner: &str, repo: &str, commits: &[String], test: bool) -> Result<String> {
+    // Indices into commits, the bad revision itself is the last one
+    let mut good = 0;
+    let mut bad = commits.len();
+    let mut skipped = Vec::new();
+    while bad - good > 1 {
+        let Some(candidate) = (good + 1..bad)
+            .filter(|index| !skipped.contains(index))
+            .min_by_key(|index| index.abs_diff((good + bad) / 2))
+        else {
+            return Err(eyre!(
+                "Only skipped commits are left between {} and {}",
+                commits[good],
+                commits[bad - 1]
+            ));
+        };
+
+        let revision = &commits[candidate - 1];
+        println!("{} commits left, trying {revision}", bad - good - 1);
+        if let Err(error) = try_revision(input, owner, repo, revision, test) {
+            eprintln!("{error}");
+        }
+
+        match judge(revision)? {
+            Some(true) => good = candidate,
+            Some(false) => bad = candidate,
+            None => skipped.push(candidate),
+        }
+    }
+
+    Ok(commits[bad - 1].clone())
+}
+
+// Filled before the first override, the handler below can only use what's prepared up front
+static ORIGINAL_LOCK: OnceLock<Vec<u8>> = OnceLock::new();
+
+// Ctrl-C in the middle of a build must not leave the overridden lock behind. Only async signal
+// safe calls are allowed here, so the lock is written back with plain open and write.
+extern "C" fn restore_lock(signal: libc::c_int) {
+    if let Some(lock) = ORIGINAL_LOCK.get() {
+        unsafe {
+            let fd = libc::open(c"flake.lock".as_ptr(), libc::O_WRONLY | libc::O_TRUNC);
+            if fd >= 0 {
+                libc::write(fd, lock.as_ptr().cast(), lock.len());
+                libc::close(fd);
+            }
+        }
+    }
+
+    unsafe { libc::_exit(128 + signal) };
+}
+
+pub fn bisect(input: &str, good: &str, bad: &str, test: bool) -> Result<()> {
+    let original_lock = fs::read_to_string("flake.lock")?;
+    ORIGINAL_LOCK.get_or_init(|| original_lock.as_bytes().to_vec());
+    // Handlers reset to the default in children, so nix and nh still stop on Ctrl-C
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        let handler = restore_lock as extern "C" fn(libc::c_int);
+        unsafe { libc::signal(signal, handler as libc::sighandler_t) };
+    }
+
+    let lock: Value = serde_json::from_str(&original_lock)?;
+    let original = &lock["nodes"][input]["original"];
+    let (Some("github"), Some(owner), Some(repo)) = (
+        original["type"].as_str(),
+        original["owner"].as_str(),
+        original["repo"].as_str(),
+    ) else {
+        return Err(eyre!(
+            "Only github inputs can be bisected, {input} is not one"
+        ));
+    };
+
+    let commits = commits_between(owner, repo, good, bad)?;
+    if commits.is_empty() {
+        return Err(eyre!("There are no commits between {good} and {bad}"));
+    }
+
+    let result = search(input, owner, repo, &commits, test);
+    // The bisection only borrows the lock, whatever the outcome
+    fs::write("flake.lock", original_lock)?;
+    let revision = result?;
+    println!("The first bad commit is https://github.com/{owner}/{repo}/commit/{revision}");
+    Ok(())
+}
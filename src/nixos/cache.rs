@@ -0,0 +1,35 @@
+use std::{fs, path::Path};
+
+use clap::{Subcommand, ValueEnum};
+use color_eyre::{Result, eyre::OptionExt};
+
+use crate::command::run_command;
+
+#[derive(Subcommand)]
+pub enum CacheAction {
+    // Pushes the running system
+    Push,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CacheTool {
+    Cachix,
+    Attic,
+}
+
+// Both take the cache name followed by the paths, pushing their whole closure
+pub fn push(tool: CacheTool, cache: &str, system: &Path) -> Result<()> {
+    let system = fs::canonicalize(system)?;
+    let command = match tool {
+        CacheTool::Cachix => "cachix",
+        CacheTool::Attic => "attic",
+    };
+    run_command(command, ["push", cache, system.to_str().unwrap()])
+}
+
+pub fn cache_action(action: CacheAction, tool: CacheTool, cache: Option<&str>) -> Result<()> {
+    let cache = cache.ok_or_eyre("No cache given, pass --cache")?;
+    match action {
+        CacheAction::Push => push(tool, cache, Path::new("/run/current-system")),
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use color_eyre::{Result, eyre::eyre};
+use serde_json::Value;
+
+use crate::command::{run_command, run_command_with_stdio};
+
+const CURRENT_SYSTEM: &str = "/run/current-system";
+
+fn closure(system: &str) -> Result<Vec<String>> {
+    let paths =
+        run_command_with_stdio("nix-store", ["--query", "--requisites", system], true, None)?;
+    Ok(String::from_utf8(paths)?
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+// The part after the hash, like firefox-128.0
+fn name(path: &str) -> &str {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    file_name
+        .split_once('-')
+        .map_or(file_name, |(_, name)| name)
+}
+
+fn is_subsequence(query: &str, name: &str) -> bool {
+    let mut name = name.chars();
+    query
+        .chars()
+        .all(|character| name.any(|other| other == character))
+}
+
+// Exact package names win over substrings, which win over loose subsequence matches
+fn find_path<'a>(paths: &'a [String], query: &str) -> Option<&'a String> {
+    let query = query.to_lowercase();
+    paths
+        .iter()
+        .filter_map(|path| {
+            let name = name(path).to_lowercase();
+            let rank = if name == query || name.starts_with(&format!("{query}-")) {
+                0
+            } else if name.contains(&query) {
+                1
+            } else if is_subsequence(&query, &name) {
+                2
+            } else {
+                return None;
+            };
+            Some((rank, name.len(), path))
+        })
+        .min()
+        .map(|(_, _, path)| path)
+}
+
+pub fn why(package: &str) -> Result<()> {
+    let paths = closure(CURRENT_SYSTEM)?;
+    let path = find_path(&paths, package)
+        .ok_or_else(|| eyre!("Nothing in the system closure matches {package}"))?;
+    println!("Matched {path}");
+    run_command("nix", ["why-depends", CURRENT_SYSTEM, path])
+}
+
+struct PathSize {
+    path: String,
+    size: u64,
+    closure_size: u64,
+}
+
+// Newer nix prints an object keyed by path, older versions a list with a path field
+fn path_info<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Vec<PathSize>> {
+    let args = ["path-info", "--size", "--closure-size", "--json"]
+        .into_iter()
+        .chain(args);
+    let output = run_command_with_stdio("nix", args, true, None)?;
+    let info: Value = serde_json::from_slice(&output)?;
+    let entries: Vec<(String, &Value)> = match &info {
+        Value::Object(paths) => paths
+            .iter()
+            .map(|(path, info)| (path.clone(), info))
+            .collect(),
+        Value::Array(paths) => paths
+            .iter()
+            .filter_map(|info| Some((info["path"].as_str()?.to_string(), info)))
+            .collect(),
+        _ => Vec::new(),
+    };
+    Ok(entries
+        .into_iter()
+        .map(|(path, info)| PathSize {
+            path,
+            size: info["narSize"].as_u64().unwrap_or_default(),
+            closure_size: info["closureSize"].as_u64().unwrap_or_default(),
+        })
+        .collect())
+}
+
+fn path_sizes(system: &str) -> Result<Vec<PathSize>> {
+    path_info(["--recursive", system])
+}
+
+// Remote systems are looked up in their own store, like ssh://host
+pub fn closure_size(path: &str, store: Option<&str>) -> Result<u64> {
+    let store = store.into_iter().flat_map(|store| ["--store", store]);
+    let info = path_info(store.chain([path]))?;
+    Ok(info.first().map_or(0, |info| info.closure_size))
+}
+
+pub fn human(bytes: i128) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes.unsigned_abs() as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    let sign = if bytes < 0 { "-" } else { "" };
+    format!("{sign}{size:.1} {}", UNITS[unit])
+}
+
+pub fn size(system: &Path, limit: usize, previous: Option<&Path>) -> Result<()> {
+    let system = system.to_str().unwrap();
+    let mut paths = path_sizes(system)?;
+    paths.sort_by_key(|path| std::cmp::Reverse(path.size));
+    let total: u64 = paths.iter().map(|path| path.size).sum();
+    println!("{:>10}  {:>10}  PATH", "SIZE", "CLOSURE");
+    for path in paths.iter().take(limit) {
+        println!(
+            "{:>10}  {:>10}  {}",
+            human(path.size.into()),
+            human(path.closure_size.into()),
+            path.path
+        );
+    }
+
+    println!("Total closure size {}", human(total.into()));
+    let Some(previous) = previous else {
+        return Ok(());
+    };
+
+    // Paths are compared by name, the hash changes with every rebuild
+    let sizes_by_name = |paths: &[PathSize]| {
+        let mut sizes = HashMap::new();
+        for path in paths {
+            *sizes.entry(name(&path.path).to_string()).or_insert(0i128) += i128::from(path.size);
+        }
+        sizes
+    };
+    let old_paths = path_sizes(previous.to_str().unwrap())?;
+    let old = sizes_by_name(&old_paths);
+    let new = sizes_by_name(&paths);
+    let mut changes: Vec<_> = old
+        .keys()
+        .chain(new.keys())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .map(|name| {
+            let delta = new.get(name).unwrap_or(&0) - old.get(name).unwrap_or(&0);
+            (delta, name)
+        })
+        .filter(|(delta, _)| *delta != 0)
+        .collect();
+    changes.sort_by_key(|(delta, _)| std::cmp::Reverse(delta.abs()));
+    let old_total: u64 = old_paths.iter().map(|path| path.size).sum();
+    println!(
+        "\nChange since {}: {}",
+        previous.display(),
+        human(i128::from(total) - i128::from(old_total))
+    );
+    for (delta, name) in changes.into_iter().take(limit) {
+        println!("{:>10}  {name}", human(delta));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use color_eyre::Result;
+
+use super::Activation;
+use crate::command::{run_command, run_command_with_stdio};
+
+fn output<'a>(command: &'a str, args: impl IntoIterator<Item = &'a str>) -> Result<String> {
+    let output = run_command_with_stdio(command, args, true, None)?;
+    Ok(String::from_utf8(output)?.trim().to_string())
+}
+
+// Same steps as nixos-rebuild --target-host, spelled out so the build can happen on either side
+pub fn deploy(
+    configuration: &str,
+    target_host: &str,
+    build_remote: bool,
+    activation: Activation,
+    specialisation: Option<&str>,
+) -> Result<()> {
+    let toplevel = format!(".#nixosConfigurations.{configuration}.config.system.build.toplevel");
+    let store = format!("ssh://{target_host}");
+    let system = match build_remote {
+        true => {
+            // Only the derivation is copied, its dependencies are fetched by the remote
+            let derivation = output("nix", ["eval", "--raw", &format!("{toplevel}.drvPath")])?;
+            run_command("nix", ["copy", "--derivation", "--to", &store, &derivation])?;
+            output("ssh", [target_host, "nix-store", "--realise", &derivation])?
+        }
+        false => {
+            let args = ["build", &toplevel, "--no-link", "--print-out-paths"];
+            let system = output("nix", args)?;
+            run_command("nix", ["copy", "--to", &store, &system])?;
+            system
+        }
+    };
+
+    // test activates without adding a generation
+    if !matches!(activation, Activation::Test) {
+        let args = [
+            target_host,
+            "sudo",
+            "nix-env",
+            "--profile",
+            "/nix/var/nix/profiles/system",
+            "--set",
+            &system,
+        ];
+        run_command("ssh", args)?;
+    }
+
+    // The profile keeps pointing at the base system, its specialisations live inside it
+    let switch = match specialisation {
+        Some(name) => format!("{system}/specialisation/{name}/bin/switch-to-configuration"),
+        None => format!("{system}/bin/switch-to-configuration"),
+    };
+    run_command("ssh", [target_host, "sudo", &switch, activation.name()])?;
+    let message = format!("Deployed {configuration} to {target_host}");
+    run_command("notify-send", ["NixOS", &message, "-t", "6000"])
+}
@@ -0,0 +1,231 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+use clap::Subcommand;
+use color_eyre::{
+    Result,
+    eyre::{OptionExt, eyre},
+};
+
+use crate::command::{not_found, run_command, run_command_with_stdio};
+
+const PROFILES: &str = "/nix/var/nix/profiles";
+
+#[derive(Subcommand)]
+pub enum GenerationsAction {
+    List,
+    Diff {
+        from: u32,
+        to: u32,
+    },
+    Delete {
+        #[arg(long)]
+        older_than: u32,
+    },
+}
+
+struct Generation {
+    number: u32,
+    created: DateTime<Local>,
+    version: String,
+    current: bool,
+}
+
+fn link(number: u32) -> PathBuf {
+    Path::new(PROFILES).join(format!("system-{number}-link"))
+}
+
+// Every generation is a system-N-link symlink next to the system profile
+fn generations() -> Result<Vec<Generation>> {
+    let current = fs::canonicalize("/run/current-system")?;
+    let mut generations = Vec::new();
+    for entry in fs::read_dir(PROFILES)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(number) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("system-")?.strip_suffix("-link"))
+            .and_then(|number| number.parse().ok())
+        else {
+            continue;
+        };
+
+        let link = entry.path();
+        let target = fs::canonicalize(&link)?;
+        let version = fs::read_to_string(target.join("nixos-version")).unwrap_or_default();
+        generations.push(Generation {
+            number,
+            created: fs::symlink_metadata(&link)?.modified()?.into(),
+            version: version.trim().to_string(),
+            current: target == current,
+        });
+    }
+
+    generations.sort_by_key(|generation| generation.number);
+    Ok(generations)
+}
+
+// The system profile points at the newest generation even after booting an older one
+pub fn latest() -> Result<u32> {
+    let target = fs::read_link(Path::new(PROFILES).join("system"))?;
+    target
+        .to_str()
+        .and_then(|name| name.strip_prefix("system-")?.strip_suffix("-link"))
+        .and_then(|number| number.parse().ok())
+        .ok_or_else(|| eyre!("Unexpected system profile target {}", target.display()))
+}
+
+// The generation before the one that is running
+pub fn previous() -> Result<PathBuf> {
+    let generations = generations()?;
+    let current = generations
+        .iter()
+        .position(|generation| generation.current)
+        .ok_or_eyre("The running system is not a generation")?;
+    let previous = current
+        .checked_sub(1)
+        .ok_or_eyre("There is no generation before the running one")?;
+    Ok(link(generations[previous].number))
+}
+
+fn list() -> Result<()> {
+    println!(
+        "{:>5}  {:<16}  {:<32}  CURRENT",
+        "GEN", "CREATED", "VERSION"
+    );
+    for generation in generations()? {
+        println!(
+            "{:>5}  {:<16}  {:<32}  {}",
+            generation.number,
+            generation.created.format("%Y-%m-%d %H:%M"),
+            generation.version,
+            if generation.current { "*" } else { "" }
+        );
+    }
+
+    Ok(())
+}
+
+// nvd prints a friendlier summary, nix itself is the fallback when it isn't installed
+pub fn diff_closures(from: &Path, to: &Path) -> Result<String> {
+    let (from, to) = (from.to_str().unwrap(), to.to_str().unwrap());
+    let output = match run_command_with_stdio("nvd", ["diff", from, to], true, None) {
+        Err(error) if not_found(&error) => {
+            let args = ["store", "diff-closures", from, to];
+            run_command_with_stdio("nix", args, true, None)
+        }
+        result => result,
+    }?;
+    Ok(String::from_utf8(output)?)
+}
+
+// nvd marks every package line, diff-closures only lists version changes
+pub fn summary(diff: &str) -> String {
+    let count = |marker: &str| {
+        diff.lines()
+            .filter(|line| line.trim_start().starts_with(marker))
+            .count()
+    };
+    let counts = [
+        (count("[U"), "upgraded"),
+        (count("[D"), "downgraded"),
+        (count("[A"), "added"),
+        (count("[R"), "removed"),
+        (
+            diff.lines().filter(|line| line.contains('→')).count(),
+            "changed",
+        ),
+    ];
+    let summary: Vec<_> = counts
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, kind)| format!("{count} {kind}"))
+        .collect();
+    match summary.is_empty() {
+        true => "No package changes".to_string(),
+        false => summary.join(", "),
+    }
+}
+
+const LOADER_ENTRIES: &str = "/boot/loader/entries";
+
+// systemd-boot names them nixos-generation-N.conf, with a -specialisation-NAME suffix for those
+fn stale_boot_entries() -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(LOADER_ENTRIES) {
+        Ok(entries) => entries,
+        // GRUB keeps all entries in one generated file
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    let numbers: Vec<_> = generations()?
+        .iter()
+        .map(|generation| generation.number)
+        .collect();
+    let mut stale = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let number = path
+            .file_stem()
+            .and_then(|name| name.to_str()?.strip_prefix("nixos-generation-"))
+            .and_then(|rest| rest.split('-').next()?.parse::<u32>().ok());
+        if number.is_some_and(|number| !numbers.contains(&number)) {
+            stale.push(path);
+        }
+    }
+
+    stale.sort();
+    Ok(stale)
+}
+
+// The bootloader installer rewrites the menu from the generations that still exist
+pub fn boot_clean(dry_run: bool) -> Result<()> {
+    let stale = stale_boot_entries()?;
+    for entry in &stale {
+        println!("{}", entry.display());
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    let switch = Path::new(PROFILES).join("system/bin/switch-to-configuration");
+    run_command("sudo", [switch.to_str().unwrap(), "boot"])?;
+    let left = stale_boot_entries()?.len();
+    println!("Removed {} boot entries", stale.len().saturating_sub(left));
+    Ok(())
+}
+
+pub fn generations_action(action: GenerationsAction) -> Result<()> {
+    match action {
+        GenerationsAction::List => list(),
+        GenerationsAction::Diff { from, to } => {
+            for number in [from, to] {
+                if !link(number).exists() {
+                    return Err(eyre!("Generation {number} does not exist"));
+                }
+            }
+
+            print!("{}", diff_closures(&link(from), &link(to))?);
+            Ok(())
+        }
+        GenerationsAction::Delete { older_than } => {
+            let profile = Path::new(PROFILES).join("system");
+            run_command(
+                "sudo",
+                [
+                    "nix-env",
+                    "--profile",
+                    profile.to_str().unwrap(),
+                    "--delete-generations",
+                    &format!("{older_than}d"),
+                ],
+            )?;
+            list()
+        }
+    }
+}
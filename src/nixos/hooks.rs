@@ -0,0 +1,60 @@
+use std::{env, process::Command};
+
+use clap::Args;
+use color_eyre::Result;
+
+use crate::command::CommandFailed;
+
+// Shell commands, run from the flake with FLAKE, and DEVICE and GENERATION where they are known
+#[derive(Args)]
+pub struct Hooks {
+    #[arg(long, env = "NIXOS_PRE_EDIT")]
+    pre_edit: Option<String>,
+    // A failing pre-switch hook skips that device
+    #[arg(long, env = "NIXOS_PRE_SWITCH")]
+    pre_switch: Option<String>,
+    #[arg(long, env = "NIXOS_POST_SWITCH")]
+    post_switch: Option<String>,
+    #[arg(long, env = "NIXOS_POST_PUSH")]
+    post_push: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+pub enum Hook {
+    PreEdit,
+    PreSwitch,
+    PostSwitch,
+    PostPush,
+}
+
+impl Hooks {
+    pub fn run(&self, hook: Hook, device: Option<&str>, generation: Option<u32>) -> Result<()> {
+        let command = match hook {
+            Hook::PreEdit => &self.pre_edit,
+            Hook::PreSwitch => &self.pre_switch,
+            Hook::PostSwitch => &self.post_switch,
+            Hook::PostPush => &self.post_push,
+        };
+        let Some(command) = command else {
+            return Ok(());
+        };
+
+        let mut shell = Command::new("sh");
+        shell
+            .args(["-c", command])
+            .env("FLAKE", env::current_dir()?);
+        if let Some(device) = device {
+            shell.env("DEVICE", device);
+        }
+
+        if let Some(generation) = generation {
+            shell.env("GENERATION", generation.to_string());
+        }
+
+        let status = shell.status()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(CommandFailed::new(command, status).into()),
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::{env, fmt};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{Result, eyre::eyre};
+use serde_json::Value;
+
+use crate::command::{run_command, run_command_with_stdio};
+
+#[derive(Clone, PartialEq)]
+pub struct Locked {
+    revision: String,
+    date: Option<String>,
+}
+
+impl fmt::Display for Locked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.date {
+            Some(date) => write!(f, "{} ({date})", self.revision),
+            None => write!(f, "{}", self.revision),
+        }
+    }
+}
+
+pub struct InputChange {
+    name: String,
+    from: Option<Locked>,
+    to: Option<Locked>,
+}
+
+impl fmt::Display for InputChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) => write!(f, "{}: {from} -> {to}", self.name),
+            (None, Some(to)) => write!(f, "{}: added at {to}", self.name),
+            (Some(from), None) => write!(f, "{}: removed, was {from}", self.name),
+            (None, None) => write!(f, "{}", self.name),
+        }
+    }
+}
+
+// Inputs without a git revision, like tarballs, are told apart by their hash
+fn inputs(lock: &str) -> Result<Vec<(String, Locked)>> {
+    if lock.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let lock: Value = serde_json::from_str(lock)?;
+    let Some(nodes) = lock["nodes"].as_object() else {
+        return Ok(Vec::new());
+    };
+
+    Ok(nodes
+        .iter()
+        .filter(|(name, _)| *name != "root")
+        .filter_map(|(name, node)| {
+            let locked = &node["locked"];
+            let revision = match locked["rev"].as_str() {
+                Some(rev) => rev.get(..7).unwrap_or(rev),
+                None => locked["narHash"].as_str()?,
+            };
+            let date = locked["lastModified"]
+                .as_i64()
+                .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+                .map(|date| date.format("%Y-%m-%d").to_string());
+            let locked = Locked {
+                revision: revision.to_string(),
+                date,
+            };
+            Some((name.clone(), locked))
+        })
+        .collect())
+}
+
+pub fn diff(old: &str, new: &str) -> Result<Vec<InputChange>> {
+    let old = inputs(old)?;
+    let new = inputs(new)?;
+    let find = |inputs: &[(String, Locked)], name: &str| {
+        inputs
+            .iter()
+            .find(|(input, _)| input == name)
+            .map(|(_, locked)| locked.clone())
+    };
+
+    let mut names: Vec<_> = old.iter().chain(&new).map(|(name, _)| name).collect();
+    names.sort();
+    names.dedup();
+    Ok(names
+        .into_iter()
+        .map(|name| InputChange {
+            name: name.clone(),
+            from: find(&old, name),
+            to: find(&new, name),
+        })
+        .filter(|change| change.from != change.to)
+        .collect())
+}
+
+pub struct Staleness {
+    name: String,
+    locked: DateTime<Utc>,
+    upstream: DateTime<Utc>,
+}
+
+impl Staleness {
+    fn days_behind(&self) -> i64 {
+        (self.upstream - self.locked).num_days().max(0)
+    }
+}
+
+impl fmt::Display for Staleness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let locked = self.locked.format("%Y-%m-%d");
+        match self.days_behind() {
+            0 => write!(f, "{}: up to date ({locked})", self.name),
+            days => write!(
+                f,
+                "{}: {days} days behind, locked {locked}, upstream {}",
+                self.name,
+                self.upstream.format("%Y-%m-%d")
+            ),
+        }
+    }
+}
+
+pub fn github_api(path: &str) -> Result<Value> {
+    let url = format!("https://api.github.com/{path}");
+    let mut request = ureq::get(&url).header("User-Agent", "scripts");
+    // Unauthenticated requests are limited to 60 an hour
+    if let Ok(token) = env::var("GITHUB_TOKEN") {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+
+    Ok(serde_json::from_str(
+        &request.call()?.body_mut().read_to_string()?,
+    )?)
+}
+
+fn github_date(original: &Value) -> Result<DateTime<Utc>> {
+    let owner = original["owner"].as_str().unwrap_or_default();
+    let repo = original["repo"].as_str().unwrap_or_default();
+    let reference = original["ref"].as_str().unwrap_or("HEAD");
+    let commit = github_api(&format!("repos/{owner}/{repo}/commits/{reference}"))?;
+    let date = commit["commit"]["committer"]["date"]
+        .as_str()
+        .ok_or_else(|| eyre!("No commit date for {owner}/{repo}"))?;
+    Ok(DateTime::parse_from_rfc3339(date)?.to_utc())
+}
+
+fn gitlab_date(original: &Value) -> Result<DateTime<Utc>> {
+    let host = original["host"].as_str().unwrap_or("gitlab.com");
+    let owner = original["owner"].as_str().unwrap_or_default();
+    let repo = original["repo"].as_str().unwrap_or_default();
+    let reference = original["ref"].as_str().unwrap_or("HEAD");
+    let url =
+        format!("https://{host}/api/v4/projects/{owner}%2F{repo}/repository/commits/{reference}");
+    let commit: Value =
+        serde_json::from_str(&ureq::get(&url).call()?.body_mut().read_to_string()?)?;
+    let date = commit["committed_date"]
+        .as_str()
+        .ok_or_else(|| eyre!("No commit date for {owner}/{repo}"))?;
+    Ok(DateTime::parse_from_rfc3339(date)?.to_utc())
+}
+
+// Anything without a forge API is fetched by nix, which is slower but knows every input type
+fn metadata_date(original: &Value) -> Result<Option<DateTime<Utc>>> {
+    let url = original["url"].as_str().unwrap_or_default();
+    let flake_ref = match original["type"].as_str() {
+        Some("git") => match original["ref"].as_str() {
+            Some(reference) => format!("git+{url}?ref={reference}"),
+            None => format!("git+{url}"),
+        },
+        Some("tarball") => url.to_string(),
+        Some("indirect") => original["id"].as_str().unwrap_or_default().to_string(),
+        // Local paths have no upstream to fall behind
+        _ => return Ok(None),
+    };
+    let args = ["flake", "metadata", "--json", "--refresh", &flake_ref];
+    let metadata: Value =
+        serde_json::from_slice(&run_command_with_stdio("nix", args, true, None)?)?;
+    Ok(metadata["lastModified"]
+        .as_i64()
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0)))
+}
+
+fn staleness(name: &str, node: &Value) -> Result<Option<Staleness>> {
+    let Some(locked) = node["locked"]["lastModified"]
+        .as_i64()
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+    else {
+        return Ok(None);
+    };
+
+    let original = &node["original"];
+    // Pinned revisions are behind on purpose
+    if original["rev"].is_string() {
+        return Ok(None);
+    }
+
+    let upstream = match original["type"].as_str() {
+        Some("github") => github_date(original)?,
+        Some("gitlab") => gitlab_date(original)?,
+        _ => match metadata_date(original)? {
+            Some(upstream) => upstream,
+            None => return Ok(None),
+        },
+    };
+    Ok(Some(Staleness {
+        name: name.to_string(),
+        locked,
+        upstream,
+    }))
+}
+
+// One unreachable forge shouldn't hide how stale the other inputs are
+pub fn outdated(lock: &str, notify_after: Option<i64>) -> Result<()> {
+    let lock: Value = serde_json::from_str(lock)?;
+    let Some(nodes) = lock["nodes"].as_object() else {
+        return Ok(());
+    };
+
+    let mut inputs = Vec::new();
+    for (name, node) in nodes.iter().filter(|(name, _)| *name != "root") {
+        match staleness(name, node) {
+            Ok(Some(input)) => inputs.push(input),
+            Ok(None) => {}
+            Err(error) => eprintln!("{name}: {error}"),
+        }
+    }
+
+    inputs.sort_by_key(|input| std::cmp::Reverse(input.days_behind()));
+    for input in &inputs {
+        println!("{input}");
+    }
+
+    let Some(days) = notify_after else {
+        return Ok(());
+    };
+
+    let stale: Vec<_> = inputs
+        .iter()
+        .filter(|input| input.days_behind() > days)
+        .map(ToString::to_string)
+        .collect();
+    if stale.is_empty() {
+        return Ok(());
+    }
+
+    run_command(
+        "notify-send",
+        ["Outdated flake inputs", &stale.join("\n"), "-t", "6000"],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(nodes: &str) -> String {
+        format!(r#"{{"nodes": {{"root": {{"inputs": {{}}}}, {nodes}}}, "version": 7}}"#)
+    }
+
+    const NIXPKGS: &str =
+        r#""nixpkgs": {"locked": {"rev": "aaaaaaaaaa", "lastModified": 1700000000}}"#;
+    const HOME: &str = r#""home-manager": {"locked": {"rev": "bbbbbbbbbb"}}"#;
+
+    fn changes(old: &str, new: &str) -> Vec<String> {
+        diff(old, new)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn added_removed_and_changed() {
+        let old = lock(&format!("{NIXPKGS}, {HOME}"));
+        let new = lock(concat!(
+            r#""nixpkgs": {"locked": {"rev": "ccccccccccc", "lastModified": 1710000000}}, "#,
+            r#""tarball": {"locked": {"narHash": "sha256-x"}}"#
+        ));
+        assert_eq!(
+            changes(&old, &new),
+            [
+                "home-manager: removed, was bbbbbbb",
+                "nixpkgs: aaaaaaa (2023-11-14) -> ccccccc (2024-03-09)",
+                "tarball: added at sha256-x",
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_inputs_are_left_out() {
+        let lock = lock(&format!("{NIXPKGS}, {HOME}"));
+        assert!(changes(&lock, &lock).is_empty());
+    }
+
+    #[test]
+    fn missing_lock_counts_as_empty() {
+        assert_eq!(changes("", &lock(HOME)), ["home-manager: added at bbbbbbb"]);
+        assert!(diff("{", "").is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use color_eyre::Result;
+use serde_json::Value;
+
+use crate::command::run_command_with_stdio;
+
+// Only the parts that survive serialisation, a default can be a function or a package
+const OPTION_INFO: &str = "option: let
+  default = builtins.tryEval (builtins.toJSON option.default);
+in {
+  description = option.description or null;
+  type = option.type.description or null;
+  default =
+    if option ? defaultText then option.defaultText
+    else if option ? default && default.success then default.value
+    else null;
+  declarations = map toString (option.declarations or [ ]);
+}";
+
+fn eval(installable: &str, apply: Option<&str>) -> Result<Value> {
+    let args = ["eval", "--json", installable]
+        .into_iter()
+        .chain(apply.into_iter().flat_map(|apply| ["--apply", apply]));
+    let output = run_command_with_stdio("nix", args, true, None)?;
+    Ok(serde_json::from_slice(&output)?)
+}
+
+// Literal expressions and markdown docs are wrapped in an attrset with the text inside
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        Value::Object(object) if object.contains_key("text") => text(&object["text"]),
+        value => value.to_string(),
+    }
+}
+
+pub fn option(host: &str, path: &str) -> Result<()> {
+    let configuration = format!(".#nixosConfigurations.{host}");
+    let info = eval(
+        &format!("{configuration}.options.{path}"),
+        Some(OPTION_INFO),
+    )?;
+    let value = match eval(&format!("{configuration}.config.{path}"), None) {
+        Ok(value) => serde_json::to_string_pretty(&value)?,
+        Err(error) => format!("can't be shown as json: {error}"),
+    };
+
+    println!("Value: {value}");
+    if !info["default"].is_null() {
+        println!("Default: {}", text(&info["default"]));
+    }
+
+    if !info["type"].is_null() {
+        println!("Type: {}", text(&info["type"]));
+    }
+
+    if !info["description"].is_null() {
+        println!("\n{}", text(&info["description"]).trim());
+    }
+
+    if let Some(declarations) = info["declarations"].as_array() {
+        println!();
+        for declaration in declarations {
+            println!("Declared in {}", text(declaration));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    process::{Command, Stdio},
+};
+
+use color_eyre::Result;
+use serde_json::Value;
+
+use crate::command::CommandFailed;
+
+// Activity and result types from nix's logging.hh
+const COPY_PATHS: u64 = 103;
+const BUILDS: u64 = 104;
+const BUILD: u64 = 105;
+const FILE_TRANSFER: u64 = 101;
+const PROGRESS: u64 = 105;
+const ERROR: u64 = 0;
+const WARNING: u64 = 1;
+
+#[derive(Default)]
+struct Progress {
+    activities: HashMap<u64, (u64, String)>,
+    builds: (u64, u64),
+    downloads: (u64, u64),
+    status_shown: bool,
+}
+
+impl Progress {
+    fn current(&self) -> Option<&str> {
+        self.activities
+            .values()
+            .find(|(kind, _)| *kind == BUILD)
+            .or_else(|| {
+                self.activities
+                    .values()
+                    .find(|(kind, _)| *kind == FILE_TRANSFER)
+            })
+            .map(|(_, text)| text.as_str())
+    }
+
+    fn status(&self) -> String {
+        let (built, builds) = self.builds;
+        let (downloaded, downloads) = self.downloads;
+        let mut status = format!("[built {built}/{builds}] [downloaded {downloaded}/{downloads}]");
+        if let Some(current) = self.current() {
+            status.push(' ');
+            status.push_str(current);
+        }
+
+        status
+    }
+
+    // The status line is redrawn in place and cleared before anything else is printed
+    fn clear(&mut self, stderr: &mut impl Write) -> io::Result<()> {
+        if self.status_shown {
+            write!(stderr, "\r\x1b[K")?;
+            self.status_shown = false;
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, stderr: &mut impl Write) -> io::Result<()> {
+        self.clear(stderr)?;
+        let status: String = self.status().chars().take(terminal_width()).collect();
+        write!(stderr, "{status}")?;
+        stderr.flush()?;
+        self.status_shown = true;
+        Ok(())
+    }
+
+    fn handle(&mut self, event: &Value, stderr: &mut impl Write) -> io::Result<()> {
+        let id = event["id"].as_u64().unwrap_or_default();
+        match event["action"].as_str() {
+            Some("start") => {
+                let kind = event["type"].as_u64().unwrap_or_default();
+                let text = event["text"].as_str().unwrap_or_default();
+                self.activities.insert(id, (kind, shorten(text)));
+            }
+            Some("stop") => {
+                self.activities.remove(&id);
+            }
+            Some("result") if event["type"].as_u64() == Some(PROGRESS) => {
+                let fields = &event["fields"];
+                let done = fields[0].as_u64().unwrap_or_default();
+                let expected = fields[1].as_u64().unwrap_or_default();
+                match self.activities.get(&id).map(|(kind, _)| *kind) {
+                    Some(BUILDS) => self.builds = (done, expected),
+                    Some(COPY_PATHS) => self.downloads = (done, expected),
+                    _ => {}
+                }
+            }
+            Some("msg")
+                if event["level"]
+                    .as_u64()
+                    .is_some_and(|level| level <= WARNING) =>
+            {
+                self.clear(stderr)?;
+                let prefix = match event["level"].as_u64() {
+                    Some(ERROR) => "error",
+                    _ => "warning",
+                };
+                let message = event["msg"].as_str().unwrap_or_default();
+                writeln!(stderr, "{prefix}: {message}")?;
+            }
+            _ => return Ok(()),
+        }
+
+        self.draw(stderr)
+    }
+}
+
+fn terminal_width() -> usize {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    match unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 if size.ws_col > 0 => size.ws_col.into(),
+        _ => 80,
+    }
+}
+
+// Store paths are long, the name after the hash is what's interesting
+fn shorten(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            let word = word.trim_matches(|character| character == '\'' || character == '"');
+            match word.strip_prefix("/nix/store/") {
+                Some(path) => path.split_once('-').map_or(path, |(_, name)| name),
+                None => word,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Anything that isn't a nix log line, like nh's own messages, is passed through
+pub fn run(command: &str, args: &[&str]) -> Result<()> {
+    let mut child = Command::new(command)
+        .args(args)
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut progress = Progress::default();
+    let mut stderr = io::stderr().lock();
+    for line in BufReader::new(child.stderr.take().unwrap()).lines() {
+        let line = line?;
+        match line
+            .strip_prefix("@nix ")
+            .and_then(|event| serde_json::from_str(event).ok())
+        {
+            Some(event) => progress.handle(&event, &mut stderr)?,
+            None => {
+                progress.clear(&mut stderr)?;
+                writeln!(stderr, "{line}")?;
+            }
+        }
+    }
+
+    progress.clear(&mut stderr)?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(CommandFailed::new(command, status).into());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use color_eyre::{Result, eyre::eyre};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::command::{not_found, run_command_with_stdio};
+
+#[derive(Deserialize)]
+struct Package {
+    version: String,
+    description: String,
+}
+
+pub struct SearchResult {
+    attribute: String,
+    version: String,
+    description: String,
+}
+
+// Keyed like legacyPackages.x86_64-linux.firefox, only the last part goes into a config
+pub fn search(query: &str) -> Result<Vec<SearchResult>> {
+    let output = run_command_with_stdio("nix", ["search", "nixpkgs", query, "--json"], true, None)?;
+    let packages: HashMap<String, Package> = serde_json::from_slice(&output)?;
+    let mut results: Vec<_> = packages
+        .into_iter()
+        .map(|(key, package)| SearchResult {
+            attribute: key.splitn(3, '.').nth(2).unwrap_or(&key).to_string(),
+            version: package.version,
+            description: package.description,
+        })
+        .collect();
+    results.sort_by(|a, b| a.attribute.cmp(&b.attribute));
+    Ok(results)
+}
+
+pub fn format(results: &[SearchResult]) -> String {
+    let width = results
+        .iter()
+        .map(|result| result.attribute.len())
+        .max()
+        .unwrap_or_default();
+    results
+        .iter()
+        .map(|result| {
+            format!(
+                "{:<width$}  {:<12}  {}\n",
+                result.attribute, result.version, result.description
+            )
+        })
+        .collect()
+}
+
+// The list an attribute goes into, environment.systemPackages or home.packages win over others
+// like fonts.packages when a file has several. Also tells whether the list is inside with pkgs;
+fn packages_list(contents: &str) -> Option<(usize, bool)> {
+    let pattern = Regex::new(r"([\w.]*[pP]ackages)\s*=\s*(?:with\s+([\w.]+)\s*;\s*)?\[").unwrap();
+    let lists: Vec<_> = pattern.captures_iter(contents).collect();
+    let preferred: Vec<_> = lists
+        .iter()
+        .filter(|list| matches!(&list[1], "environment.systemPackages" | "home.packages"))
+        .collect();
+    let list = match (lists.as_slice(), preferred.as_slice()) {
+        ([list], _) => list,
+        (_, [list]) => *list,
+        _ => return None,
+    };
+    let with_pkgs = list.get(2).is_some_and(|scope| scope.as_str() == "pkgs");
+    Some((list.get(0)?.end() - 1, with_pkgs))
+}
+
+// Search results are nixpkgs attributes, outside with pkgs; they need the prefix
+fn entry(attribute: &str, with_pkgs: bool) -> String {
+    match with_pkgs {
+        true => attribute.to_string(),
+        false => format!("pkgs.{attribute}"),
+    }
+}
+
+// Skips nested lists, strings and comments, any of which can hold a bracket of their own
+fn closing_bracket(contents: &str, open: usize) -> Option<usize> {
+    let bytes = contents.as_bytes();
+    let mut depth = 0;
+    let mut index = open;
+    while index < bytes.len() {
+        let rest = &contents[index..];
+        if let Some(comment) = rest.strip_prefix('#') {
+            index += 1 + comment.find('\n')?;
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            index += 2 + comment.find("*/")? + 1;
+        } else if let Some(string) = rest.strip_prefix("''") {
+            index += 2 + string.find("''")? + 1;
+        } else if rest.starts_with('"') {
+            let mut end = 1;
+            while bytes.get(index + end)? != &b'"' {
+                end += if bytes[index + end] == b'\\' { 2 } else { 1 };
+            }
+            index += end;
+        } else if rest.starts_with('[') {
+            depth += 1;
+        } else if rest.starts_with(']') {
+            depth -= 1;
+            if depth == 0 {
+                return Some(index);
+            }
+        }
+
+        index += 1;
+    }
+
+    None
+}
+
+// Goes on its own line indented like the entry above it, or next to the others in a one line list
+fn insert(contents: &str, open: usize, close: usize, attribute: &str) -> String {
+    let line_start = contents[..close].rfind('\n').map_or(0, |index| index + 1);
+    let own_line = line_start > open && contents[line_start..close].trim().is_empty();
+    if !own_line {
+        let (before, after) = contents.split_at(close);
+        return match before.ends_with(char::is_whitespace) {
+            true => format!("{before}{attribute} {after}"),
+            false => format!("{before} {attribute}{after}"),
+        };
+    }
+
+    let (before, after) = contents.split_at(line_start);
+    let previous = before[open + 1..]
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty());
+    let indent: String = match previous {
+        Some(line) => line.chars().take_while(|c| c.is_whitespace()).collect(),
+        None => format!("{}  ", &contents[line_start..close]),
+    };
+    format!("{before}{indent}{attribute}\n{after}")
+}
+
+// Parsing catches broken syntax, evaluating the host's system catches names that aren't in scope
+fn check(path: &str, host: &str) -> Result<()> {
+    run_command_with_stdio("nix-instantiate", ["--parse", path], true, None)?;
+    let derivation = format!(".#nixosConfigurations.{host}.config.system.build.toplevel.drvPath");
+    run_command_with_stdio("nix", ["eval", "--raw", &derivation], true, None).map(|_| ())
+}
+
+// Expects to run in the flake, the file being relative to it
+pub fn add_package(file: &Path, attribute: &str, host: &str) -> Result<()> {
+    let contents = fs::read_to_string(file)?;
+    let (open, with_pkgs) = packages_list(&contents).ok_or_else(|| {
+        eyre!(
+            "{} has no single packages = [ ... ] list to add {attribute} to",
+            file.display()
+        )
+    })?;
+    let close = closing_bracket(&contents, open)
+        .ok_or_else(|| eyre!("The packages list in {} is never closed", file.display()))?;
+    let entry = entry(attribute, with_pkgs);
+    fs::write(file, insert(&contents, open, close, &entry))?;
+
+    // A broken edit goes back to what was there, without nix the edit is trusted
+    let path = file.to_str().unwrap();
+    match check(path, host) {
+        Err(error) if !not_found(&error) => {
+            fs::write(file, contents)?;
+            Err(error.wrap_err(format!("Adding {attribute} would break {path}")))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(contents: &str) -> Option<String> {
+        let (open, with_pkgs) = packages_list(contents)?;
+        let close = closing_bracket(contents, open)?;
+        Some(insert(contents, open, close, &entry("ripgrep", with_pkgs)))
+    }
+
+    #[test]
+    fn one_line_list() {
+        assert_eq!(
+            add("{ home.packages = with pkgs; [ git vim ]; }").unwrap(),
+            "{ home.packages = with pkgs; [ git vim ripgrep ]; }"
+        );
+    }
+
+    #[test]
+    fn prefixed_unless_inside_with_pkgs() {
+        let contents = "{\n  environment.systemPackages = with pkgs; [\n    git\n  ];\n}\n";
+        assert_eq!(
+            add(contents).unwrap(),
+            "{\n  environment.systemPackages = with pkgs; [\n    git\n    ripgrep\n  ];\n}\n"
+        );
+        assert_eq!(
+            add("{ home.packages = with lib; [ ]; }").unwrap(),
+            "{ home.packages = with lib; [ pkgs.ripgrep ]; }"
+        );
+    }
+
+    #[test]
+    fn own_line_indented_like_the_entry_above() {
+        let contents = "{\n  environment.systemPackages = [\n    pkgs.git\n  ];\n}\n";
+        assert_eq!(
+            add(contents).unwrap(),
+            "{\n  environment.systemPackages = [\n    pkgs.git\n    pkgs.ripgrep\n  ];\n}\n"
+        );
+    }
+
+    #[test]
+    fn brackets_in_comments_strings_and_nested_lists() {
+        let contents =
+            "{\n  home.packages = [\n    # not [ this\n    (f [ \"]\" ])\n  ];\n  x = [ ];\n}\n";
+        assert_eq!(
+            add(contents).unwrap(),
+            "{\n  home.packages = [\n    # not [ this\n    (f [ \"]\" ])\n    pkgs.ripgrep\n  ];\n  x = [ ];\n}\n"
+        );
+    }
+
+    #[test]
+    fn preferred_list_wins() {
+        let contents = "{ fonts.packages = [ a ]; environment.systemPackages = [ b ]; }";
+        assert_eq!(
+            add(contents).unwrap(),
+            "{ fonts.packages = [ a ]; environment.systemPackages = [ b pkgs.ripgrep ]; }"
+        );
+    }
+
+    #[test]
+    fn unrecognised_layouts_are_refused() {
+        assert!(add("{ imports = [ ./a.nix ]; }").is_none());
+        assert!(add("{ fonts.packages = [ a ]; other.packages = [ b ]; }").is_none());
+        assert!(add("{ home.packages = [ a ").is_none());
+    }
+}
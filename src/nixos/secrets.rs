@@ -0,0 +1,63 @@
+use std::{env, fs, path::Path};
+
+use clap::Subcommand;
+use color_eyre::{Result, eyre::eyre};
+
+use crate::command::{run_command, run_command_with_stdio};
+
+#[derive(Subcommand)]
+pub enum SecretsAction {
+    Rekey,
+}
+
+fn tracked_files() -> Result<Vec<String>> {
+    let files = run_command_with_stdio("git", ["ls-files"], true, None)?;
+    Ok(String::from_utf8(files)?
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+// sops keeps its metadata under a top level sops key in every format it writes
+fn is_sops_encrypted(path: &str) -> bool {
+    fs::read_to_string(path).is_ok_and(|contents| {
+        contents.contains("\nsops:")
+            || contents.contains("\"sops\": {")
+            || contents.contains("sops_version=")
+    })
+}
+
+// Both agenix and sops-nix are handled, some flakes use both
+pub fn rekey() -> Result<()> {
+    let files = tracked_files()?;
+    let mut rekeyed = false;
+    for rules in files.iter().filter(|file| file.ends_with("secrets.nix")) {
+        let dir = Path::new(rules).parent().unwrap_or(Path::new("."));
+        let flake = env::current_dir()?;
+        env::set_current_dir(flake.join(dir))?;
+        let result = run_command("agenix", ["--rekey"]);
+        env::set_current_dir(flake)?;
+        result?;
+        rekeyed = true;
+    }
+
+    if files.iter().any(|file| file.ends_with(".sops.yaml")) {
+        for secret in files.iter().filter(|file| is_sops_encrypted(file)) {
+            run_command("sops", ["updatekeys", "--yes", secret])?;
+            rekeyed = true;
+        }
+    }
+
+    match rekeyed {
+        true => Ok(()),
+        false => Err(eyre!(
+            "No agenix secrets.nix or sops encrypted files in the flake"
+        )),
+    }
+}
+
+pub fn secrets_action(action: SecretsAction) -> Result<()> {
+    match action {
+        SecretsAction::Rekey => rekey(),
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use color_eyre::Result;
+use zbus::{blocking::Connection, proxy, zvariant::Value};
+
+use crate::command::run_command;
+
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+// notify-send can't report back which action was clicked, so this talks to the notification daemon
+// directly. Blocks until an action is invoked or the notification is closed.
+pub fn notify_with_actions(
+    summary: &str,
+    body: &str,
+    icon: &str,
+    timeout_ms: i32,
+    actions: &[(&str, &str)],
+) -> Result<Option<String>> {
+    let connection = Connection::session()?;
+    let proxy = NotificationsProxyBlocking::new(&connection)?;
+    // Subscribe before sending so that a quick click can't be missed
+    let signals = proxy.inner().receive_all_signals()?;
+    let actions: Vec<_> = actions
+        .iter()
+        .flat_map(|(key, label)| [*key, *label])
+        .collect();
+    let id = proxy.notify(
+        "scripts",
+        0,
+        icon,
+        summary,
+        body,
+        &actions,
+        HashMap::new(),
+        timeout_ms,
+    )?;
+
+    for signal in signals {
+        let header = signal.header();
+        match header.member().map(|member| member.as_str()) {
+            Some("ActionInvoked") => {
+                let (signal_id, action): (u32, String) = signal.body().deserialize()?;
+                if signal_id == id {
+                    return Ok(Some(action));
+                }
+            }
+            Some("NotificationClosed") => {
+                let (signal_id, _reason): (u32, u32) = signal.body().deserialize()?;
+                if signal_id == id {
+                    return Ok(None);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(None)
+}
+
+// Notifications with the same tag replace each other, so holding a key shows one bar instead of a
+// stack of them
+pub fn osd(summary: &str, tag: &str, icon: &str, value: u32) -> Result<()> {
+    let tag = format!("string:x-canonical-private-synchronous:{tag}");
+    let value = format!("int:value:{value}");
+    let args = [summary, "-h", &tag, "-h", &value, "-i", icon, "-t", "1500"];
+    run_command("notify-send", args)
+}
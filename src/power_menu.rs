@@ -0,0 +1,102 @@
+use clap::Args;
+use color_eyre::{Result, eyre::eyre};
+
+use crate::{
+    command::{self, run_command, spawn_detached},
+    geometry::Cancelled,
+};
+
+#[derive(Args)]
+pub struct PowerMenuOptions {
+    #[arg(long, env = "POWER_MENU_PICKER", default_value = "fuzzel --dmenu")]
+    picker: String,
+    #[arg(long, env = "POWER_MENU_LOCKER", default_value = "swaylock -f")]
+    locker: String,
+    // Asks again before anything that loses the session
+    #[arg(long)]
+    confirm: bool,
+}
+
+#[derive(Clone, Copy)]
+enum PowerAction {
+    Lock,
+    Logout,
+    Suspend,
+    Hibernate,
+    Reboot,
+    Shutdown,
+}
+
+const ACTIONS: [PowerAction; 6] = [
+    PowerAction::Lock,
+    PowerAction::Logout,
+    PowerAction::Suspend,
+    PowerAction::Hibernate,
+    PowerAction::Reboot,
+    PowerAction::Shutdown,
+];
+
+impl PowerAction {
+    fn name(self) -> &'static str {
+        match self {
+            PowerAction::Lock => "Lock",
+            PowerAction::Logout => "Logout",
+            PowerAction::Suspend => "Suspend",
+            PowerAction::Hibernate => "Hibernate",
+            PowerAction::Reboot => "Reboot",
+            PowerAction::Shutdown => "Shutdown",
+        }
+    }
+
+    // Suspending keeps everything open, the others close it
+    fn destructive(self) -> bool {
+        matches!(
+            self,
+            PowerAction::Logout | PowerAction::Reboot | PowerAction::Shutdown
+        )
+    }
+
+    fn run(self, locker: &str) -> Result<()> {
+        match self {
+            PowerAction::Lock => {
+                let mut locker = locker.split_whitespace();
+                let command = locker.next().ok_or_else(|| eyre!("Empty locker command"))?;
+                spawn_detached(command, locker)?;
+                Ok(())
+            }
+            PowerAction::Logout => {
+                for outcome in swayipc::Connection::new()?.run_command("exit")? {
+                    outcome?;
+                }
+
+                Ok(())
+            }
+            PowerAction::Suspend => run_command("systemctl", ["suspend"]),
+            PowerAction::Hibernate => run_command("systemctl", ["hibernate"]),
+            PowerAction::Reboot => run_command("systemctl", ["reboot"]),
+            PowerAction::Shutdown => run_command("systemctl", ["poweroff"]),
+        }
+    }
+}
+
+pub fn power_menu(options: PowerMenuOptions) -> Result<()> {
+    let choices: String = ACTIONS
+        .iter()
+        .map(|action| format!("{}\n", action.name()))
+        .collect();
+    let picked = command::pick(&options.picker, &choices)?;
+    let action = ACTIONS
+        .into_iter()
+        .find(|action| action.name() == picked)
+        .ok_or_else(|| eyre!("Unknown action {picked}"))?;
+
+    // No comes first so an accidental enter does nothing
+    if options.confirm && action.destructive() {
+        let answer = command::pick(&options.picker, &format!("No\n{}\n", action.name()))?;
+        if answer != action.name() {
+            return Err(Cancelled.into());
+        }
+    }
+
+    action.run(&options.locker)
+}
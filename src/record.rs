@@ -0,0 +1,283 @@
+use std::{env, fs, path::Path, thread, time::Duration};
+
+use chrono::Local;
+use clap::{Args, Subcommand, ValueEnum};
+use color_eyre::{
+    Result,
+    eyre::{ContextCompat, eyre},
+};
+use serde_json::json;
+
+use crate::{
+    clipboard,
+    command::{run_command, run_command_with_stdio, spawn_detached},
+    geometry, state,
+};
+
+#[derive(Subcommand)]
+pub enum RecordArea {
+    Fullscreen,
+    Window,
+    Region {
+        #[arg(long)]
+        slurp_fg: String,
+        #[arg(long)]
+        slurp_bg: String,
+    },
+    Stop,
+    // Prints waybar custom module json
+    Status,
+    // Started next to wf-recorder
+    #[command(hide = true)]
+    Refresh,
+}
+
+#[derive(Args)]
+pub struct RecordOptions {
+    // Converted once the recording is stopped, the video is kept next to it
+    #[arg(long, value_enum)]
+    convert: Option<Animation>,
+    // default records what is playing, anything else must be a pactl source name
+    #[arg(long, default_value = "none")]
+    audio: String,
+    #[arg(long, conflicts_with = "audio")]
+    mic: bool,
+    // Refreshes a waybar module with a matching signal setting on start and stop
+    #[arg(long)]
+    waybar_signal: Option<u8>,
+    #[arg(long)]
+    with_borders: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Animation {
+    Gif,
+    Webp,
+}
+
+impl Animation {
+    fn extension(self) -> &'static str {
+        match self {
+            Animation::Gif => "gif",
+            Animation::Webp => "webp",
+        }
+    }
+}
+
+struct Recording {
+    pid: String,
+    path: String,
+    convert: Option<Animation>,
+}
+
+// The pidfile is written when the recording starts, so its age is the elapsed time
+fn status(pidfile: &Path) -> Result<String> {
+    let status = match running_recording(pidfile)? {
+        Some(recording) => {
+            let elapsed = fs::metadata(pidfile)?.modified()?.elapsed()?.as_secs();
+            json!({
+                "text": format!("{:02}:{:02}", elapsed / 60, elapsed % 60),
+                "tooltip": recording.path,
+                "class": "recording",
+                "alt": "recording",
+            })
+        }
+        None => json!({ "text": "", "class": "idle", "alt": "idle" }),
+    };
+    Ok(status.to_string())
+}
+
+fn update_status(pidfile: &Path, options: &RecordOptions) -> Result<()> {
+    let path = state::runtime_path("record.json");
+    state::write_atomic(&path, status(pidfile)?.as_bytes())?;
+    if let Some(signal) = options.waybar_signal {
+        // pkill fails when waybar is not running, which is fine
+        let _ = run_command("pkill", [&format!("-RTMIN+{signal}"), "waybar"]);
+    }
+
+    Ok(())
+}
+
+// Keeps the elapsed time in record.json current for waybar, which only reads the file
+fn refresh(pidfile: &Path, options: &RecordOptions) -> Result<()> {
+    while running_recording(pidfile)?.is_some() {
+        update_status(pidfile, options)?;
+        thread::sleep(Duration::from_secs(1));
+    }
+
+    update_status(pidfile, options)
+}
+
+fn running_recording(pidfile: &Path) -> Result<Option<Recording>> {
+    let Ok(contents) = fs::read_to_string(pidfile) else {
+        return Ok(None);
+    };
+
+    let mut lines = contents.lines();
+    let (Some(pid), Some(path)) = (lines.next(), lines.next()) else {
+        return Err(eyre!("Malformed recording pidfile"));
+    };
+    // A stale pidfile is left behind when wf-recorder dies on its own
+    if !Path::new("/proc").join(pid).exists() {
+        fs::remove_file(pidfile)?;
+        return Ok(None);
+    }
+
+    let convert = lines
+        .next()
+        .map(|convert| Animation::from_str(convert, false).map_err(|error| eyre!(error)))
+        .transpose()?;
+    Ok(Some(Recording {
+        pid: pid.to_string(),
+        path: path.to_string(),
+        convert,
+    }))
+}
+
+// Scaled down and at a lower framerate, full resolution animations get huge
+fn convert(video: &str, animation: Animation) -> Result<String> {
+    const FILTER: &str = "fps=15,scale='min(iw,960)':-1:flags=lanczos";
+    let output = Path::new(video)
+        .with_extension(animation.extension())
+        .to_str()
+        .unwrap()
+        .to_string();
+    let filter = match animation {
+        // A palette generated from the video itself looks much better than the default one
+        Animation::Gif => format!("{FILTER},split[a][b];[a]palettegen[p];[b][p]paletteuse"),
+        Animation::Webp => FILTER.to_string(),
+    };
+    let mut args = vec!["-y", "-loglevel", "error", "-i", video, "-vf", &filter];
+    if let Animation::Webp = animation {
+        args.extend(["-c:v", "libwebp", "-quality", "70", "-loop", "0"]);
+    }
+
+    args.push(&output);
+    run_command("ffmpeg", args)?;
+    Ok(output)
+}
+
+fn pactl(args: &[&str]) -> Result<String> {
+    let output = run_command_with_stdio("pactl", args.iter().copied(), true, None)?;
+    Ok(String::from_utf8(output)?.trim().to_string())
+}
+
+fn audio_source(options: &RecordOptions) -> Result<Option<String>> {
+    if options.mic {
+        return pactl(&["get-default-source"]).map(Some);
+    }
+
+    match options.audio.as_str() {
+        "none" => Ok(None),
+        "default" => Ok(Some(format!("{}.monitor", pactl(&["get-default-sink"])?))),
+        source => {
+            let sources = pactl(&["list", "short", "sources"])?;
+            let sources: Vec<_> = sources
+                .lines()
+                .filter_map(|line| line.split('\t').nth(1))
+                .collect();
+            if !sources.contains(&source) {
+                return Err(eyre!(
+                    "Unknown audio source {source}, available sources are:\n{}",
+                    sources.join("\n")
+                ));
+            }
+
+            Ok(Some(source.to_string()))
+        }
+    }
+}
+
+fn stop(recording: Recording) -> Result<()> {
+    // wf-recorder finalizes the file on SIGINT
+    run_command("kill", ["-INT", &recording.pid])?;
+    let Some(animation) = recording.convert else {
+        return run_command(
+            "notify-send",
+            [
+                "Recording",
+                &format!("Recording saved as {}", recording.path),
+                "-t",
+                "6000",
+            ],
+        );
+    };
+
+    // The video is only complete once wf-recorder has exited
+    while Path::new("/proc").join(&recording.pid).exists() {
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let path = convert(&recording.path, animation)?;
+    clipboard::copy_text(&path)?;
+    run_command(
+        "notify-send",
+        [
+            "Recording",
+            &format!("Recording converted to {path} and copied to clipboard"),
+            "-t",
+            "6000",
+        ],
+    )
+}
+
+pub fn record(area: RecordArea, options: RecordOptions) -> Result<()> {
+    let pidfile = state::runtime_path("record.pid");
+    if let RecordArea::Status = area {
+        println!("{}", status(&pidfile)?);
+        return Ok(());
+    }
+
+    if let RecordArea::Refresh = area {
+        return refresh(&pidfile, &options);
+    }
+
+    if let Some(recording) = running_recording(&pidfile)? {
+        fs::remove_file(&pidfile)?;
+        update_status(&pidfile, &options)?;
+        return stop(recording);
+    }
+
+    if let RecordArea::Stop = area {
+        return Err(eyre!("No recording is running"));
+    }
+
+    let mut path = dirs::video_dir().wrap_err("Cannot determine videos dir")?;
+    path.push("recordings");
+    fs::create_dir_all(&path)?;
+    const FMT: &str = "recording-%Y-%m-%d-%H:%M:%S.mp4";
+    path.push(Local::now().format(FMT).to_string());
+    let path = path.to_str().unwrap();
+
+    let geometry = match area {
+        RecordArea::Stop | RecordArea::Status | RecordArea::Refresh => unreachable!(),
+        RecordArea::Fullscreen => None,
+        RecordArea::Window => Some(geometry::focused_window(options.with_borders)?),
+        RecordArea::Region { slurp_fg, slurp_bg } => {
+            Some(geometry::select_region(&slurp_fg, &slurp_bg)?)
+        }
+    };
+
+    // wf-recorder only accepts the device glued to the flag
+    let audio = audio_source(&options)?.map(|source| format!("--audio={source}"));
+    let args = ["-f", path]
+        .into_iter()
+        .chain(geometry.iter().flat_map(|region| ["-g", region.as_str()]))
+        .chain(audio.as_deref());
+    let pid = spawn_detached("wf-recorder", args)?.id();
+    let mut contents = format!("{pid}\n{path}");
+    if let Some(animation) = options.convert {
+        contents.push('\n');
+        contents.push_str(animation.extension());
+    }
+
+    fs::write(&pidfile, contents)?;
+    let exe = env::current_exe()?;
+    let signal = options.waybar_signal.map(|signal| signal.to_string());
+    let signal = signal
+        .iter()
+        .flat_map(|signal| ["--waybar-signal", signal.as_str()]);
+    let args = ["record"].into_iter().chain(signal).chain(["refresh"]);
+    spawn_detached(exe.to_str().unwrap(), args)?;
+    Ok(())
+}
@@ -0,0 +1,351 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::Read,
+    os::fd::AsFd,
+    process,
+};
+
+use color_eyre::{
+    Result,
+    eyre::{OptionExt, eyre},
+};
+use image::{DynamicImage, RgbaImage, imageops};
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+    globals::{GlobalList, GlobalListContents, registry_queue_init},
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_output::{self, WlOutput},
+        wl_registry::{self, WlRegistry},
+        wl_shm::{Format, WlShm},
+        wl_shm_pool::WlShmPool,
+    },
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, Flags, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+use crate::geometry::Rect;
+
+struct BufferInfo {
+    format: WEnum<Format>,
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+#[derive(Default)]
+struct Frame {
+    buffer: Option<BufferInfo>,
+    buffer_done: bool,
+    y_invert: bool,
+    ready: bool,
+    failed: bool,
+}
+
+#[derive(Default)]
+struct State {
+    output_names: Vec<Option<String>>,
+    frame: Frame,
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlOutput, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wl_output::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Name { name } = event {
+            state.output_names[*index] = Some(name);
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let frame = &mut state.frame;
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => {
+                frame.buffer = Some(BufferInfo {
+                    format,
+                    width,
+                    height,
+                    stride,
+                })
+            }
+            zwlr_screencopy_frame_v1::Event::Flags { flags } => {
+                frame.y_invert = flags == WEnum::Value(Flags::YInvert)
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => frame.buffer_done = true,
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => frame.ready = true,
+            zwlr_screencopy_frame_v1::Event::Failed => frame.failed = true,
+            _ => {}
+        }
+    }
+}
+
+wayland_client::delegate_noop!(State: ignore WlShm);
+wayland_client::delegate_noop!(State: ignore WlShmPool);
+wayland_client::delegate_noop!(State: ignore WlBuffer);
+wayland_client::delegate_noop!(State: ZwlrScreencopyManagerV1);
+
+struct Screencopy {
+    queue: EventQueue<State>,
+    state: State,
+    outputs: Vec<WlOutput>,
+    shm: WlShm,
+    manager: ZwlrScreencopyManagerV1,
+}
+
+impl Screencopy {
+    fn new() -> Result<Self> {
+        let connection = Connection::connect_to_env()?;
+        let (globals, mut queue): (GlobalList, _) = registry_queue_init(&connection)?;
+        let handle = queue.handle();
+        let shm = globals.bind(&handle, 1..=1, ())?;
+        let manager = globals.bind(&handle, 1..=3, ())?;
+        let outputs: Vec<WlOutput> = globals
+            .contents()
+            .clone_list()
+            .into_iter()
+            .filter(|global| global.interface == "wl_output")
+            .enumerate()
+            .map(|(index, global)| {
+                // Output names were only added in version 4
+                globals
+                    .registry()
+                    .bind(global.name, global.version.min(4), &handle, index)
+            })
+            .collect();
+        let mut state = State {
+            output_names: vec![None; outputs.len()],
+            ..State::default()
+        };
+        queue.roundtrip(&mut state)?;
+        Ok(Screencopy {
+            queue,
+            state,
+            outputs,
+            shm,
+            manager,
+        })
+    }
+
+    fn output(&self, name: &str) -> Result<&WlOutput> {
+        self.state
+            .output_names
+            .iter()
+            .position(|output| output.as_deref() == Some(name))
+            .map(|index| &self.outputs[index])
+            .ok_or_else(|| eyre!("Output {name} not found"))
+    }
+
+    fn copy(&mut self, frame: ZwlrScreencopyFrameV1) -> Result<RgbaImage> {
+        self.state.frame = Frame::default();
+        // Version 3 announces every buffer type before buffer_done, older ones only send shm
+        let version = self.manager.version();
+        while self.state.frame.buffer.is_none() || (version >= 3 && !self.state.frame.buffer_done) {
+            self.queue.blocking_dispatch(&mut self.state)?;
+        }
+
+        let info = self.state.frame.buffer.take().unwrap();
+        let size = info.stride * info.height;
+        let path = dirs::runtime_dir()
+            .unwrap_or_else(env::temp_dir)
+            .join(format!("scripts-screencopy-{}", process::id()));
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        fs::remove_file(&path)?;
+        file.set_len(size.into())?;
+
+        let handle = self.queue.handle();
+        let pool = self.shm.create_pool(file.as_fd(), size as i32, &handle, ());
+        let format = match info.format {
+            WEnum::Value(format) => format,
+            WEnum::Unknown(format) => return Err(eyre!("Unknown shm format {format}")),
+        };
+        let buffer = pool.create_buffer(
+            0,
+            info.width as i32,
+            info.height as i32,
+            info.stride as i32,
+            format,
+            &handle,
+            (),
+        );
+        frame.copy(&buffer);
+        while !self.state.frame.ready && !self.state.frame.failed {
+            self.queue.blocking_dispatch(&mut self.state)?;
+        }
+
+        frame.destroy();
+        buffer.destroy();
+        pool.destroy();
+        if self.state.frame.failed {
+            return Err(eyre!("Compositor failed to copy the frame"));
+        }
+
+        // The compositor wrote straight into the shared file, no need to map it
+        let mut bytes = vec![0; size as usize];
+        file.read_exact(&mut bytes)?;
+        let image = to_rgba(&bytes, &info, format)?;
+        Ok(match self.state.frame.y_invert {
+            true => imageops::flip_vertical(&image),
+            false => image,
+        })
+    }
+}
+
+// Formats are little endian, so argb8888 is stored as bgra
+fn to_rgba(bytes: &[u8], info: &BufferInfo, format: Format) -> Result<RgbaImage> {
+    let swap = match format {
+        Format::Argb8888 | Format::Xrgb8888 => true,
+        Format::Abgr8888 | Format::Xbgr8888 => false,
+        format => return Err(eyre!("Unsupported shm format {format:?}")),
+    };
+    let opaque = matches!(format, Format::Xrgb8888 | Format::Xbgr8888);
+
+    let mut image = RgbaImage::new(info.width, info.height);
+    for (y, row) in bytes.chunks(info.stride as usize).enumerate() {
+        for (x, pixel) in row.chunks(4).take(info.width as usize).enumerate() {
+            let [mut red, green, mut blue, mut alpha] = pixel.try_into()?;
+            if swap {
+                (red, blue) = (blue, red);
+            }
+
+            if opaque {
+                alpha = 255;
+            }
+
+            image.put_pixel(x as u32, y as u32, [red, green, blue, alpha].into());
+        }
+    }
+
+    Ok(image)
+}
+
+pub fn capture_output(name: &str, cursor: bool) -> Result<DynamicImage> {
+    let mut screencopy = Screencopy::new()?;
+    let handle = screencopy.queue.handle();
+    let output = screencopy.output(name)?;
+    let frame = screencopy
+        .manager
+        .capture_output(cursor.into(), output, &handle, ());
+    Ok(screencopy.copy(frame)?.into())
+}
+
+// Regions are captured from the output containing their top left corner
+pub fn capture_region(region: Rect, cursor: bool) -> Result<DynamicImage> {
+    let output = swayipc::Connection::new()?
+        .get_outputs()?
+        .into_iter()
+        .filter(|output| output.active)
+        .find(|output| {
+            let rect = output.rect;
+            (rect.x..rect.x + rect.width).contains(&region.x)
+                && (rect.y..rect.y + rect.height).contains(&region.y)
+        })
+        .ok_or_eyre("Region is not on any output")?;
+
+    let mut screencopy = Screencopy::new()?;
+    let handle = screencopy.queue.handle();
+    let wl_output = screencopy.output(&output.name)?;
+    let frame = screencopy.manager.capture_output_region(
+        cursor.into(),
+        wl_output,
+        region.x - output.rect.x,
+        region.y - output.rect.y,
+        region.width,
+        region.height,
+        &handle,
+        (),
+    );
+    Ok(screencopy.copy(frame)?.into())
+}
+
+// Like grim, outputs are laid out at the highest scale so that nothing gets downscaled
+pub fn capture_all(cursor: bool) -> Result<DynamicImage> {
+    let outputs: Vec<_> = swayipc::Connection::new()?
+        .get_outputs()?
+        .into_iter()
+        .filter(|output| output.active)
+        .collect();
+    let scale = outputs
+        .iter()
+        .filter_map(|output| output.scale)
+        .fold(1.0, f64::max);
+    let left = outputs
+        .iter()
+        .map(|output| output.rect.x)
+        .min()
+        .unwrap_or(0);
+    let top = outputs
+        .iter()
+        .map(|output| output.rect.y)
+        .min()
+        .unwrap_or(0);
+    let right = outputs
+        .iter()
+        .map(|output| output.rect.x + output.rect.width)
+        .max()
+        .unwrap_or(0);
+    let bottom = outputs
+        .iter()
+        .map(|output| output.rect.y + output.rect.height)
+        .max()
+        .unwrap_or(0);
+    let to_pixels = |logical: i32| (logical as f64 * scale).round() as u32;
+
+    let mut screencopy = Screencopy::new()?;
+    let handle = screencopy.queue.handle();
+    let mut canvas = RgbaImage::new(to_pixels(right - left), to_pixels(bottom - top));
+    for output in outputs {
+        let wl_output = screencopy.output(&output.name)?;
+        let frame = screencopy
+            .manager
+            .capture_output(cursor.into(), wl_output, &handle, ());
+        let image = imageops::resize(
+            &screencopy.copy(frame)?,
+            to_pixels(output.rect.width),
+            to_pixels(output.rect.height),
+            imageops::FilterType::Triangle,
+        );
+        let x = to_pixels(output.rect.x - left).into();
+        let y = to_pixels(output.rect.y - top).into();
+        imageops::replace(&mut canvas, &image, x, y);
+    }
+
+    Ok(canvas.into())
+}
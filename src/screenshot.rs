@@ -0,0 +1,834 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    iter,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use clap::{Args, Subcommand, ValueEnum};
+use color_eyre::{
+    Result,
+    eyre::{ContextCompat, eyre},
+};
+use image::imageops;
+
+use regex::Regex;
+
+use crate::{
+    annotate::{self, Annotations, Arrow, Color, Label},
+    clipboard,
+    command::{run_command, run_command_with_stdio},
+    geometry::{self, Focus, Rect, SlurpOptions},
+    history, imaging,
+    metadata::{self, Metadata},
+    notification, screencopy, state, still, upload,
+};
+
+#[derive(Subcommand)]
+pub enum ScreenshotArea {
+    Fullscreen,
+    Window,
+    Region {
+        #[arg(long, required_unless_present = "last")]
+        slurp_fg: Option<String>,
+        #[arg(long, required_unless_present = "last")]
+        slurp_bg: Option<String>,
+        #[arg(long)]
+        freeze: bool,
+        #[arg(long, conflicts_with = "freeze")]
+        last: bool,
+        #[command(flatten)]
+        slurp: SlurpOptions,
+    },
+    Pick {
+        #[arg(long)]
+        slurp_fg: String,
+        #[arg(long)]
+        slurp_bg: String,
+    },
+    App {
+        #[arg(long = "match")]
+        pattern: Regex,
+    },
+    Scroll {
+        #[arg(long, default_value = "wtype -k Page_Down")]
+        scroll_command: String,
+        #[arg(long, default_value_t = 20)]
+        max_frames: usize,
+        #[arg(long, default_value_t = 300)]
+        settle_ms: u64,
+    },
+    Output {
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    AllOutputs,
+    Qr {
+        #[arg(long)]
+        slurp_fg: String,
+        #[arg(long)]
+        slurp_bg: String,
+    },
+    // Copies the most recent screenshot unless told otherwise
+    Last {
+        #[arg(long, group = "action")]
+        open: bool,
+        #[arg(long, group = "action")]
+        copy: bool,
+        #[arg(long, group = "action")]
+        upload: bool,
+    },
+}
+
+impl ScreenshotArea {
+    fn name(&self) -> &'static str {
+        match self {
+            ScreenshotArea::Fullscreen => "fullscreen",
+            ScreenshotArea::Window => "window",
+            ScreenshotArea::Region { .. } => "region",
+            ScreenshotArea::Pick { .. } => "pick",
+            ScreenshotArea::App { .. } => "app",
+            ScreenshotArea::Scroll { .. } => "scroll",
+            ScreenshotArea::Output { .. } => "output",
+            ScreenshotArea::AllOutputs => "all-outputs",
+            ScreenshotArea::Qr { .. } => "qr",
+            ScreenshotArea::Last { .. } => "last",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Backend {
+    Native,
+    Grim,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Ppm,
+}
+
+impl ImageFormat {
+    fn name(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Ppm => "ppm",
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Ppm => "image/x-portable-pixmap",
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ScreenshotOptions {
+    #[arg(long)]
+    edit: bool,
+    #[arg(long, default_value_t = 0)]
+    delay: u64,
+    // swappy always saves png
+    #[arg(long, value_enum, default_value = "png", conflicts_with = "edit")]
+    format: ImageFormat,
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    quality: Option<u8>,
+    #[arg(
+        long,
+        env = "SCREENSHOT_FILENAME_TEMPLATE",
+        default_value = "screenshot-%Y-%m-%d-%H:%M:%S"
+    )]
+    filename_template: String,
+    #[arg(long, conflicts_with = "no_copy")]
+    no_save: bool,
+    #[arg(long)]
+    no_copy: bool,
+    #[arg(long)]
+    cursor: bool,
+    #[arg(long)]
+    upload: bool,
+    #[arg(long, env = "SCREENSHOT_UPLOAD_URL", default_value = "https://0x0.st")]
+    upload_url: String,
+    #[arg(long)]
+    redact: bool,
+    #[arg(long, default_value = "#000000ff")]
+    redact_fg: String,
+    #[arg(long, default_value = "#ffffff40")]
+    redact_bg: String,
+    #[arg(long, env = "SCREENSHOT_DIR")]
+    dir: Option<PathBuf>,
+    #[arg(long)]
+    optimize: bool,
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["edit", "redact", "upload", "no_save"]
+    )]
+    count: u64,
+    #[arg(long, default_value_t = 500)]
+    interval: u64,
+    #[arg(long, conflicts_with_all = ["no_save", "no_copy", "upload", "count"])]
+    copy_path: bool,
+    #[arg(long)]
+    trim: bool,
+    #[arg(long, conflicts_with_all = ["upload", "copy_path", "count"])]
+    stdout: bool,
+    // Piped screenshots are only saved or copied when asked to
+    #[arg(long, requires = "stdout", conflicts_with = "no_save")]
+    save: bool,
+    #[arg(long, requires = "stdout", conflicts_with = "no_copy")]
+    copy: bool,
+    #[arg(long)]
+    stamp: Option<String>,
+    #[arg(long, value_enum, default_value = "bottom-right")]
+    stamp_corner: Corner,
+    #[arg(long, value_enum, default_value = "grim")]
+    backend: Backend,
+    #[arg(long)]
+    arrow: Vec<Arrow>,
+    #[arg(long = "box")]
+    boxes: Vec<Rect>,
+    #[arg(long)]
+    text: Vec<Label>,
+    #[arg(long, default_value = "#ff0000")]
+    annotation_color: Color,
+    #[arg(long, default_value_t = 4.0)]
+    annotation_width: f32,
+    #[arg(long)]
+    with_borders: bool,
+}
+
+fn trim(bytes: Vec<u8>, options: &ScreenshotOptions) -> Result<Vec<u8>> {
+    match options.trim {
+        true => {
+            let trimmed = imaging::trim(&imaging::decode(&bytes)?);
+            imaging::encode(&trimmed, options.format, options.quality)
+        }
+        false => Ok(bytes),
+    }
+}
+
+// Annotations and the stamp go on last, so trimming or redacting can't cut them off
+fn decorate(bytes: Vec<u8>, now: DateTime<Local>, options: &ScreenshotOptions) -> Result<Vec<u8>> {
+    let annotations = Annotations {
+        arrows: &options.arrow,
+        boxes: &options.boxes,
+        labels: &options.text,
+        color: options.annotation_color,
+        width: options.annotation_width,
+    };
+    let bytes = match annotations.is_empty() {
+        true => bytes,
+        false => {
+            let mut image = imaging::decode(&bytes)?.to_rgba8();
+            annotate::annotate(&mut image, &annotations)?;
+            imaging::encode(&image.into(), options.format, options.quality)?
+        }
+    };
+
+    match &options.stamp {
+        Some(stamp_with) => stamp(&bytes, stamp_with, now, options),
+        None => Ok(bytes),
+    }
+}
+
+pub fn screenshot(area: ScreenshotArea, mut options: ScreenshotOptions) -> Result<()> {
+    if options.stdout {
+        options.no_save = !options.save;
+        options.no_copy = !options.copy;
+    }
+
+    if let ScreenshotArea::Last { open, upload, .. } = area {
+        return last(open, upload, &options);
+    }
+
+    let area_name = area.name();
+    let dir = screenshot_dir(options.dir.as_deref())?;
+    let now = Local::now();
+    // Metadata is best effort, fullscreen captures also work outside of sway
+    let focus = geometry::focus().unwrap_or_default();
+    let metadata = metadata::Metadata::new(&focus, now);
+    let file_name = file_name(&options.filename_template, now, &focus)?;
+    let extension = options.format.name();
+    let mut frames = match area {
+        ScreenshotArea::Fullscreen => capture(&[], &options),
+        ScreenshotArea::Window => capture(
+            &[
+                "-g".to_string(),
+                geometry::focused_window(options.with_borders)?,
+            ],
+            &options,
+        ),
+        ScreenshotArea::Region { last: true, .. } => {
+            capture(&["-g".to_string(), geometry::last_region()?], &options)
+        }
+        ScreenshotArea::Region {
+            slurp_fg: Some(slurp_fg),
+            slurp_bg: Some(slurp_bg),
+            freeze: false,
+            slurp,
+            ..
+        } => {
+            let region = geometry::select_region_with(&slurp_fg, &slurp_bg, &slurp)?;
+            geometry::save_last_region(&region)?;
+            capture(&["-g".to_string(), region], &options)
+        }
+        ScreenshotArea::Region {
+            slurp_fg: Some(slurp_fg),
+            slurp_bg: Some(slurp_bg),
+            freeze: true,
+            slurp,
+            ..
+        } => freeze_region(&slurp_fg, &slurp_bg, &slurp, &options).map(|frame| vec![frame]),
+        ScreenshotArea::Region { .. } => unreachable!("clap requires slurp colors without --last"),
+        ScreenshotArea::Pick { slurp_fg, slurp_bg } => capture(
+            &[
+                "-g".to_string(),
+                geometry::pick_window(&slurp_fg, &slurp_bg, options.with_borders)?,
+            ],
+            &options,
+        ),
+        ScreenshotArea::App { pattern } => capture(
+            &[
+                "-g".to_string(),
+                geometry::matching_window(&pattern, options.with_borders)?,
+            ],
+            &options,
+        ),
+        ScreenshotArea::Scroll {
+            scroll_command,
+            max_frames,
+            settle_ms,
+        } => scroll(&scroll_command, max_frames, settle_ms, &options).map(|frame| vec![frame]),
+        ScreenshotArea::Output { output } => {
+            capture(&["-o".to_string(), geometry::output(output)?], &options)
+        }
+        ScreenshotArea::AllOutputs => {
+            let entry = |path: &Path| history_entry(path, area_name, now, &focus, None);
+            return all_outputs(&dir, &file_name, now, &metadata, entry, &options);
+        }
+        ScreenshotArea::Qr { slurp_fg, slurp_bg } => {
+            return scan_qr(&slurp_fg, &slurp_bg, options.delay);
+        }
+        ScreenshotArea::Last { .. } => unreachable!(),
+    }?;
+
+    // Editing and redacting are interactive, so clap keeps them away from bursts
+    if frames.len() > 1 {
+        let mut last = Vec::new();
+        for (index, frame) in frames.into_iter().enumerate() {
+            let path = dir.join(format!("{file_name}-{index:03}.{extension}"));
+            let frame = trim(frame, &options)?;
+            last = optimize(decorate(frame, now, &options)?, &options)?;
+            save(&path, &last, &options, &metadata)?;
+            history::record(&history_entry(&path, area_name, now, &focus, None))?;
+        }
+
+        let mut message = format!("Frames saved to {}", dir.to_str().unwrap());
+        if !options.no_copy {
+            clipboard::copy(&last, options.format.mime())?;
+            message.push_str("\nLast frame copied to clipboard");
+        }
+
+        run_command("notify-send", ["Screenshot", &message, "-t", "6000"])?;
+        return Ok(());
+    }
+
+    let path = dir.join(format!("{file_name}.{extension}"));
+    let bytes = trim(frames.remove(0), &options)?;
+
+    let bytes = match options.redact {
+        true => redact(&bytes, &options)?,
+        false => bytes,
+    };
+
+    let bytes = match options.edit {
+        true => edit_screenshot(&bytes)?,
+        false => bytes,
+    };
+
+    let bytes = optimize(decorate(bytes, now, &options)?, &options)?;
+    // Piping into other tools must not save, copy or notify behind the user's back
+    if options.stdout {
+        io::stdout().write_all(&bytes)?;
+        if options.no_save && options.no_copy {
+            return Ok(());
+        }
+    }
+
+    if !options.no_save {
+        save(&path, &bytes, &options, &metadata)?;
+    }
+
+    let url = match options.upload {
+        true => Some(upload::upload(
+            &options.upload_url,
+            path.file_name().unwrap().to_str().unwrap(),
+            options.format.mime(),
+            &bytes,
+        )?),
+        false => None,
+    };
+
+    if !options.no_save {
+        history::record(&history_entry(&path, area_name, now, &focus, url.clone()))?;
+    }
+
+    if !options.no_copy {
+        match (&url, options.copy_path) {
+            (Some(url), _) => clipboard::copy_text(url)?,
+            (None, true) => clipboard::copy_text(path.to_str().unwrap())?,
+            (None, false) => clipboard::copy(&bytes, options.format.mime())?,
+        }
+    }
+
+    let path = path.to_str().unwrap();
+    let mut message = Vec::new();
+    if !options.no_save {
+        message.push(format!("File saved as {path}"));
+    }
+
+    if let Some(url) = &url {
+        message.push(format!("Uploaded to {url}"));
+    }
+
+    if !options.no_copy {
+        message.push(match (url, options.copy_path) {
+            (Some(_), _) => "URL copied to clipboard".to_string(),
+            (None, true) => "Path copied to clipboard".to_string(),
+            (None, false) => "Copied to clipboard".to_string(),
+        });
+    }
+
+    let message = message.join("\n");
+    if options.no_save {
+        //notify-rs was slow for some reason
+        run_command("notify-send", ["Screenshot", &message, "-t", "6000"])?;
+        return Ok(());
+    }
+
+    let thumbnail = thumbnail(&bytes)?;
+    let action = notification::notify_with_actions(
+        "Screenshot",
+        &message,
+        thumbnail.to_str().unwrap(),
+        6000,
+        &[
+            ("open", "Open"),
+            ("edit", "Edit"),
+            ("folder", "Open folder"),
+            ("delete", "Delete"),
+        ],
+    )?;
+    match action.as_deref() {
+        Some("open") => run_command("xdg-open", [path])?,
+        Some("edit") => run_command("swappy", ["-f", path, "-o", path])?,
+        Some("folder") => {
+            let folder = Path::new(path).parent().unwrap();
+            run_command("xdg-open", [folder.to_str().unwrap()])?
+        }
+        Some("delete") => fs::remove_file(path)?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+// Outputs with different scales are pointless to stitch together, so each gets its own file
+fn all_outputs(
+    dir: &Path,
+    file_name: &str,
+    now: DateTime<Local>,
+    metadata: &Metadata,
+    entry: impl Fn(&Path) -> history::Entry,
+    options: &ScreenshotOptions,
+) -> Result<()> {
+    // Each of these works on a single image
+    if options.stdout || options.edit || options.redact || options.upload || options.copy_path {
+        return Err(eyre!(
+            "all-outputs can't be combined with --stdout, --edit, --redact, --upload or --copy-path"
+        ));
+    }
+
+    let outputs = swayipc::Connection::new()?.get_outputs()?;
+    countdown(options.delay)?;
+    let mut names = Vec::new();
+    // The clipboard holds a single image, the focused output's
+    let mut copied = None;
+    for output in outputs.into_iter().filter(|output| output.active) {
+        let bytes = grim(
+            &["-o".to_string(), output.name.clone()],
+            options.format,
+            options,
+        )?;
+        let bytes = optimize(decorate(trim(bytes, options)?, now, options)?, options)?;
+        if !options.no_save {
+            let path = dir.join(format!(
+                "{file_name}-{}.{}",
+                output.name,
+                options.format.name()
+            ));
+            save(&path, &bytes, options, metadata)?;
+            history::record(&entry(&path))?;
+        }
+
+        if copied.is_none() || output.focused {
+            copied = Some((output.name.clone(), bytes));
+        }
+
+        names.push(output.name);
+    }
+
+    let mut message = match options.no_save {
+        true => format!("Outputs {} captured", names.join(", ")),
+        false => format!(
+            "Outputs {} saved to {}",
+            names.join(", "),
+            dir.to_str().unwrap()
+        ),
+    };
+    if let Some((name, bytes)) = copied
+        && !options.no_copy
+    {
+        clipboard::copy(&bytes, options.format.mime())?;
+        message.push_str(&format!("\n{name} copied to clipboard"));
+    }
+
+    run_command("notify-send", ["Screenshot", &message, "-t", "6000"])?;
+    Ok(())
+}
+
+fn history_entry(
+    path: &Path,
+    area: &str,
+    now: DateTime<Local>,
+    focus: &Focus,
+    url: Option<String>,
+) -> history::Entry {
+    history::Entry {
+        path: path.to_path_buf(),
+        captured: now.to_rfc3339(),
+        area: area.to_string(),
+        app_id: focus.app_id.clone(),
+        title: focus.title.clone(),
+        url,
+    }
+}
+
+// For when the clipboard has been overwritten since
+fn last(open: bool, upload: bool, options: &ScreenshotOptions) -> Result<()> {
+    let entry = history::last()?;
+    let path = entry.path.to_str().unwrap();
+    if open {
+        return run_command("xdg-open", [path]);
+    }
+
+    let format = match entry.path.extension().and_then(OsStr::to_str) {
+        Some("jpeg" | "jpg") => ImageFormat::Jpeg,
+        Some("ppm") => ImageFormat::Ppm,
+        _ => ImageFormat::Png,
+    };
+    let bytes = fs::read(&entry.path)?;
+    let message = match upload {
+        true => {
+            // Reuploading the same file is pointless when the url is still known
+            let url = match entry.url {
+                Some(url) => url,
+                None => upload::upload(
+                    &options.upload_url,
+                    entry.path.file_name().unwrap().to_str().unwrap(),
+                    format.mime(),
+                    &bytes,
+                )?,
+            };
+            clipboard::copy_text(&url)?;
+            format!("Uploaded to {url}\nURL copied to clipboard")
+        }
+        false => {
+            clipboard::copy(&bytes, format.mime())?;
+            format!("{path} copied to clipboard")
+        }
+    };
+    run_command("notify-send", ["Screenshot", &message, "-t", "6000"])
+}
+
+// A path stamps that image, anything else is rendered as text with strftime and {hostname}
+fn stamp(
+    bytes: &[u8],
+    stamp_with: &str,
+    now: DateTime<Local>,
+    options: &ScreenshotOptions,
+) -> Result<Vec<u8>> {
+    const MARGIN: i64 = 16;
+    let mut image = imaging::decode(bytes)?.to_rgba8();
+    let stamp = match Path::new(stamp_with).is_file() {
+        true => imaging::decode(&fs::read(stamp_with)?)?.to_rgba8(),
+        false => {
+            let mut text = String::new();
+            write!(text, "{}", now.format(stamp_with))
+                .map_err(|_| eyre!("Invalid stamp {stamp_with}"))?;
+            let hostname = fs::read_to_string("/proc/sys/kernel/hostname")?;
+            let text = text.replace("{hostname}", hostname.trim());
+            imaging::label(&imaging::load_font()?, &text, 24.0)
+        }
+    };
+
+    let right = i64::from(image.width()) - i64::from(stamp.width()) - MARGIN;
+    let bottom = i64::from(image.height()) - i64::from(stamp.height()) - MARGIN;
+    let (x, y) = match options.stamp_corner {
+        Corner::TopLeft => (MARGIN, MARGIN),
+        Corner::TopRight => (right, MARGIN),
+        Corner::BottomLeft => (MARGIN, bottom),
+        Corner::BottomRight => (right, bottom),
+    };
+    imageops::overlay(&mut image, &stamp, x, y);
+    imaging::encode(&image.into(), options.format, options.quality)
+}
+
+// Notification daemons are slow to render full resolution captures as icons
+fn thumbnail(bytes: &[u8]) -> Result<PathBuf> {
+    let thumbnail = imaging::decode(bytes)?.thumbnail(256, 256);
+    let path = dirs::cache_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("scripts")
+        .join("screenshot-thumbnail.png");
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, imaging::encode(&thumbnail, ImageFormat::Png, None)?)?;
+    Ok(path)
+}
+
+// grim already compresses jpeg and ppm is meant to be raw
+fn optimize(bytes: Vec<u8>, options: &ScreenshotOptions) -> Result<Vec<u8>> {
+    match options.optimize && matches!(options.format, ImageFormat::Png) {
+        true => run_command_with_stdio("oxipng", ["--stdout", "-"], true, Some(&bytes)),
+        false => Ok(bytes),
+    }
+}
+
+// Only the saved file carries metadata, uploads and the clipboard don't leak window titles
+fn save(path: &Path, bytes: &[u8], options: &ScreenshotOptions, metadata: &Metadata) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, metadata::embed(bytes, options.format, metadata))?;
+    Ok(())
+}
+
+// Relative dirs are taken from the home dir, so that SCREENSHOT_DIR doesn't depend on the cwd
+pub fn screenshot_dir(dir: Option<&Path>) -> Result<PathBuf> {
+    let Some(dir) = dir else {
+        let pictures = dirs::picture_dir().wrap_err("Cannot determine pictures dir")?;
+        return Ok(pictures.join("screenshots"));
+    };
+
+    state::home_path(dir)
+}
+
+// The target is selected first so that menus opened during the countdown stay open
+fn capture(target: &[String], options: &ScreenshotOptions) -> Result<Vec<Vec<u8>>> {
+    countdown(options.delay)?;
+    let mut frames = Vec::new();
+    for frame in 0..options.count {
+        if frame > 0 {
+            thread::sleep(Duration::from_millis(options.interval));
+        }
+
+        frames.push(grim(target, options.format, options)?);
+    }
+
+    Ok(frames)
+}
+
+fn grim(target: &[String], format: ImageFormat, options: &ScreenshotOptions) -> Result<Vec<u8>> {
+    if let Backend::Native = options.backend {
+        let image = match target {
+            [flag, region] if flag == "-g" => {
+                screencopy::capture_region(region.parse()?, options.cursor)
+            }
+            [flag, output] if flag == "-o" => screencopy::capture_output(output, options.cursor),
+            _ => screencopy::capture_all(options.cursor),
+        }?;
+        return imaging::encode(&image, format, options.quality);
+    }
+
+    let quality = options.quality.map(|quality| quality.to_string());
+    let args = target
+        .iter()
+        .map(String::as_str)
+        .chain(["-t", format.name()])
+        .chain(options.cursor.then_some("-c"))
+        .chain(quality.iter().flat_map(|quality| ["-q", quality.as_str()]))
+        .chain(iter::once("-"));
+    run_command_with_stdio("grim", args, true, None)
+}
+
+// Slurp runs over a still image of the focused output, so moving content can be selected
+fn freeze_region(
+    slurp_fg: &str,
+    slurp_bg: &str,
+    slurp: &SlurpOptions,
+    options: &ScreenshotOptions,
+) -> Result<Vec<u8>> {
+    countdown(options.delay)?;
+    let output = geometry::focused_output()?;
+    let frozen = grim(
+        &["-o".to_string(), output.name.clone()],
+        ImageFormat::Png,
+        options,
+    )?;
+    let (region, viewer) = still::select_over(&frozen, || {
+        geometry::select_region_with(slurp_fg, slurp_bg, slurp)
+    })?;
+    geometry::save_last_region(&region)?;
+
+    let image = imaging::decode(&frozen)?;
+    let (x, y, width, height) =
+        still::to_pixels(region.parse()?, &viewer, image.width(), image.height());
+    let cropped = image.crop_imm(x, y, width, height);
+    imaging::encode(&cropped, options.format, options.quality)
+}
+
+// Scrolls the focused window between captures until it stops changing
+fn scroll(
+    scroll_command: &str,
+    max_frames: usize,
+    settle_ms: u64,
+    options: &ScreenshotOptions,
+) -> Result<Vec<u8>> {
+    let (command, args) = scroll_command
+        .split_once(' ')
+        .unwrap_or((scroll_command, ""));
+    let target = [
+        "-g".to_string(),
+        geometry::focused_window(options.with_borders)?,
+    ];
+    countdown(options.delay)?;
+
+    let mut frames = vec![grim(&target, ImageFormat::Png, options)?];
+    while frames.len() < max_frames {
+        run_command(command, args.split_whitespace())?;
+        thread::sleep(Duration::from_millis(settle_ms));
+        let frame = grim(&target, ImageFormat::Png, options)?;
+        if frames.last() == Some(&frame) {
+            break;
+        }
+
+        frames.push(frame);
+    }
+
+    let frames = frames
+        .iter()
+        .map(|frame| imaging::decode(frame))
+        .collect::<Result<Vec<_>>>()?;
+    imaging::encode(&imaging::stitch(&frames), options.format, options.quality)
+}
+
+// Every selection over the capture gets pixelated until slurp is cancelled. Any other failure
+// must not save the capture unredacted.
+fn redact(bytes: &[u8], options: &ScreenshotOptions) -> Result<Vec<u8>> {
+    let mut image = imaging::decode(bytes)?;
+    let png = imaging::encode(&image, ImageFormat::Png, None)?;
+    let (selections, output) = still::select_over(&png, || {
+        let mut selections = Vec::new();
+        loop {
+            match geometry::select_region(&options.redact_fg, &options.redact_bg) {
+                Ok(selection) => selections.push(selection.parse::<Rect>()?),
+                Err(error) if error.is::<geometry::Cancelled>() => return Ok(selections),
+                Err(error) => return Err(error),
+            }
+        }
+    })?;
+
+    for selection in selections {
+        let (x, y, width, height) =
+            still::to_pixels(selection, &output, image.width(), image.height());
+        imaging::pixelate(&mut image, x, y, width, height);
+    }
+
+    imaging::encode(&image, options.format, options.quality)
+}
+
+pub fn file_name(template: &str, now: DateTime<Local>, focus: &Focus) -> Result<String> {
+    let mut file_name = String::new();
+    write!(file_name, "{}", now.format(template))
+        .map_err(|_| eyre!("Invalid filename template {template}"))?;
+
+    // Window titles can contain anything, including path separators
+    let sanitize = |value: &str| value.replace('/', "_");
+    Ok(file_name
+        .replace("{app_id}", &sanitize(&focus.app_id))
+        .replace("{title}", &sanitize(&focus.title))
+        .replace("{output}", &sanitize(&focus.output)))
+}
+
+fn countdown(seconds: u64) -> Result<()> {
+    for remaining in (1..=seconds).rev() {
+        run_command(
+            "notify-send",
+            [
+                "Screenshot",
+                &format!("Capturing in {remaining}…"),
+                "-t",
+                "1000",
+                "-h",
+                "string:x-canonical-private-synchronous:screenshot-countdown",
+            ],
+        )?;
+        thread::sleep(Duration::from_secs(1));
+    }
+
+    Ok(())
+}
+
+fn edit_screenshot(bytes: &[u8]) -> Result<Vec<u8>> {
+    let edited = run_command_with_stdio("swappy", ["-f", "-", "-o", "-"], true, Some(bytes))?;
+    if edited.is_empty() {
+        return Err(eyre!("Swappy was closed without saving the screenshot"));
+    }
+
+    Ok(edited)
+}
+
+fn scan_qr(slurp_fg: &str, slurp_bg: &str, delay: u64) -> Result<()> {
+    let region = geometry::select_region(slurp_fg, slurp_bg)?;
+    countdown(delay)?;
+    let path = env::temp_dir().join("scripts-qr.png");
+    let path = path.to_str().unwrap();
+    run_command("grim", ["-g", &region, path])?;
+    let output = run_command_with_stdio("zbarimg", ["--raw", "-q", path], true, None);
+    fs::remove_file(path)?;
+    let payload = String::from_utf8(output?)?.trim().to_string();
+
+    clipboard::copy_text(&payload)?;
+    let is_url = payload.starts_with("http://") || payload.starts_with("https://");
+    let message = format!("Copied {payload} to clipboard");
+    let args = ["QR code", &message, "-t", "6000"]
+        .into_iter()
+        .chain(is_url.then_some(["-A", "open=Open"]).into_iter().flatten());
+    // With an action notify-send blocks until the notification is closed and prints the chosen one
+    let action = run_command_with_stdio("notify-send", args, true, None)?;
+    if String::from_utf8(action)?.trim() == "open" {
+        run_command("xdg-open", [payload.as_str()])?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1092 @@
+use std::{
+    collections::VecDeque,
+    env,
+    fs::{self, File},
+    io::{self, BufRead, Cursor, Read, Write, stdin},
+    mem,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use chrono::Local;
+use clap::{Args, Subcommand, ValueEnum};
+use color_eyre::{
+    Result,
+    eyre::{OptionExt, eyre},
+};
+use regex::Regex;
+
+use crate::{
+    clipboard,
+    command::{self, CommandFailed, run_command, run_command_with_stdio},
+    geometry, state,
+};
+
+mod html;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum KeepColors {
+    // Left in for editors that render them, like kak with ansi.kak
+    Ansi,
+    // Opened in the browser
+    Html,
+}
+
+#[derive(Args)]
+pub struct ScrollbackOptions {
+    #[arg(long, env = "EDITOR")]
+    editor_name: String,
+    // Without a value the escapes are left in for the editor
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "ansi")]
+    keep_colors: Option<KeepColors>,
+    // Replaces the arguments that open known editors at the last line
+    #[arg(long, env = "SCROLLBACK_EDITOR_ARGS", allow_hyphen_values = true)]
+    editor_args: Option<String>,
+    // Also accepted after the action, like copy --last-lines 20
+    #[arg(long, global = true)]
+    last_lines: Option<usize>,
+    // Only the output of the last command
+    #[arg(long, global = true)]
+    since_prompt: bool,
+    // Matched against cleaned lines when the shell doesn't mark its prompts with OSC 133
+    #[arg(long, env = "SCROLLBACK_PROMPT", default_value = PROMPT_PATTERN)]
+    prompt_pattern: String,
+    // Headers between the commands, or with pick only the output of the picked one
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "all")]
+    split_commands: Option<SplitCommands>,
+    #[arg(long, env = "SCROLLBACK_PICKER", default_value = "fuzzel --dmenu")]
+    section_picker: String,
+    // Writes OSC 8 hyperlinks as text (url) instead of only their text
+    #[arg(long)]
+    keep_links: bool,
+    // TUI apps leave their whole screen in the scrollback
+    #[arg(long, value_enum, default_value = "keep")]
+    alt_screen: AltScreen,
+    #[arg(long, value_enum, default_value = "auto")]
+    terminal: Terminal,
+    // Outside of kitty, like from a compositor keybind, the socket from its listen_on
+    #[arg(long, env = "KITTY_LISTEN_ON")]
+    kitty_socket: Option<String>,
+}
+
+// A user@host or a path has to come before the prompt character, so output like 100% done or a
+// # comment isn't taken for one
+const PROMPT_PATTERN: &str =
+    r"^(?:\[[^\]\n]*@[^\]\n]*\]|[\w.-]+@[\w.-]+\S*|[~/]\S*)\s?[$#%❯](?: |$)";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SplitCommands {
+    All,
+    Pick,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum AltScreen {
+    Keep,
+    Drop,
+    Only,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Terminal {
+    Auto,
+    // foot pipes the scrollback to stdin from its pipe-scrollback binding
+    Foot,
+    Kitty,
+    Wezterm,
+}
+
+impl ScrollbackOptions {
+    // Piped input wins, otherwise the terminal we run in or the focused one is asked. Keybinds
+    // start us with /dev/null as stdin, which is no terminal but no scrollback either.
+    fn terminal(&self) -> Terminal {
+        if self.terminal != Terminal::Auto {
+            return self.terminal;
+        }
+
+        let stdin_type = fs::metadata("/dev/stdin").map(|metadata| metadata.file_type());
+        if stdin_type.is_ok_and(|file_type| file_type.is_fifo() || file_type.is_file()) {
+            return Terminal::Foot;
+        }
+
+        if env::var_os("KITTY_WINDOW_ID").is_some() {
+            return Terminal::Kitty;
+        }
+
+        if env::var_os("WEZTERM_PANE").is_some() {
+            return Terminal::Wezterm;
+        }
+
+        match geometry::focus().map(|focus| focus.app_id).as_deref() {
+            Ok("kitty") => Terminal::Kitty,
+            Ok("org.wezfurlong.wezterm") => Terminal::Wezterm,
+            _ => Terminal::Foot,
+        }
+    }
+
+    fn input(&self) -> Result<Box<dyn BufRead>> {
+        let output = match self.terminal() {
+            Terminal::Auto | Terminal::Foot => return Ok(Box::new(stdin().lock())),
+            Terminal::Kitty => {
+                let to = self
+                    .kitty_socket
+                    .iter()
+                    .flat_map(|socket| ["--to", socket.as_str()]);
+                let args = ["@"].into_iter().chain(to).chain([
+                    "get-text",
+                    "--extent",
+                    "all",
+                    "--ansi",
+                    "--match",
+                    "state:focused",
+                ]);
+                run_command_with_stdio("kitten", args, true, None)?
+            }
+            // Negative lines reach into the scrollback, this is further back than it keeps
+            Terminal::Wezterm => run_command_with_stdio(
+                "wezterm",
+                [
+                    "cli",
+                    "get-text",
+                    "--escapes",
+                    "--start-line",
+                    "-1000000000",
+                ],
+                true,
+                None,
+            )?,
+        };
+        Ok(Box::new(Cursor::new(output)))
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ScrollbackAction {
+    // Opens or copies a link picked from the ones in the scrollback
+    Urls {
+        #[arg(long)]
+        copy: bool,
+        #[arg(long, env = "SCROLLBACK_PICKER", default_value = "fuzzel --dmenu")]
+        picker: String,
+    },
+    // Opens the editor at a path:line[:column] picked from compiler or test output
+    Paths {
+        #[arg(long, env = "SCROLLBACK_PICKER", default_value = "fuzzel --dmenu")]
+        picker: String,
+    },
+    // Prints the matching cleaned lines instead of opening them
+    Grep {
+        pattern: String,
+        #[arg(short = 'C', long, default_value_t = 0)]
+        context: usize,
+    },
+    // Copies the cleaned scrollback instead of opening it
+    Copy,
+    // Compares the scrollback to the one captured by the previous diff, like two test runs
+    Diff {
+        // Shown in the editor without one
+        #[arg(long, env = "SCROLLBACK_DIFF_PAGER")]
+        pager: Option<String>,
+    },
+    // Writes the cleaned scrollback to a timestamped log and copies its path
+    Save {
+        // Relative to the home dir, defaults to ~/Documents/term-logs
+        #[arg(long, env = "SCROLLBACK_DIR")]
+        dir: Option<PathBuf>,
+        // Also opens the saved log in the editor
+        #[arg(long)]
+        open: bool,
+    },
+}
+
+const ESCAPE: u8 = 0x1b;
+const BELL: u8 = 0x07;
+const BACKSPACE: u8 = 0x08;
+const SHIFT_OUT: u8 = 0x0e;
+const SHIFT_IN: u8 = 0x0f;
+
+#[derive(Clone, Copy)]
+enum State {
+    Text,
+    Escape,
+    // Character set designations like ESC ( B, which tput sgr0 emits
+    EscapeIntermediate,
+    // CSI, ESC [ followed by parameters, intermediates and a final byte
+    ControlSequence,
+    // OSC, DCS, APC and PM, terminated by BEL or ESC \
+    CommandString,
+    CommandStringEscape,
+}
+
+// Scrollbacks of verbose builds run into hundreds of megabytes, so escapes are stripped as the
+// input streams in. Sequences split between chunks are picked up where the last chunk left off.
+struct Stripper {
+    state: State,
+    started: bool,
+    // SGR sequences are only known to be one at their final byte
+    keep_colors: bool,
+    sequence: Vec<u8>,
+    // Targets of OSC 8 hyperlinks, whose text often isn't the url itself, with the line they're on
+    links: Vec<(usize, String)>,
+    // Newlines written so far, so links can be ordered among urls found in the text
+    lines: usize,
+    // Written after the link text when the link closes
+    keep_links: bool,
+    link: Option<(String, Vec<u8>)>,
+    // Held back until something other than whitespace follows, which trims the end
+    whitespace: Vec<u8>,
+    // The last character, which a backspace can still overstrike like man does for bold
+    held: Vec<u8>,
+    alt_screen: AltScreen,
+    alternate: bool,
+}
+
+impl Stripper {
+    fn new() -> Self {
+        Stripper {
+            state: State::Text,
+            started: false,
+            keep_colors: false,
+            sequence: Vec::new(),
+            links: Vec::new(),
+            lines: 0,
+            keep_links: false,
+            link: None,
+            whitespace: Vec::new(),
+            held: Vec::new(),
+            alt_screen: AltScreen::Keep,
+            alternate: false,
+        }
+    }
+
+    // Colors are left to the caller, only the editor can show them
+    fn from_options(options: &ScrollbackOptions) -> Self {
+        Stripper {
+            keep_links: options.keep_links,
+            alt_screen: options.alt_screen,
+            ..Stripper::new()
+        }
+    }
+
+    fn hidden(&self) -> bool {
+        match self.alt_screen {
+            AltScreen::Keep => false,
+            AltScreen::Drop => self.alternate,
+            AltScreen::Only => !self.alternate,
+        }
+    }
+
+    // Sections kept with --alt-screen only are told apart by an empty line
+    fn switch_screen(&mut self, alternate: bool, output: &mut Vec<u8>) {
+        if !self.hidden() {
+            output.append(&mut self.held);
+            if matches!(self.alt_screen, AltScreen::Only) && !alternate && self.alternate {
+                self.push_text(b'\n', output);
+                self.push_text(b'\n', output);
+            }
+        }
+
+        self.alternate = alternate;
+    }
+
+    // Whitespace is only ever held while no character is, so the order is kept
+    fn push_text(&mut self, byte: u8, output: &mut Vec<u8>) {
+        if self.hidden() {
+            return;
+        }
+
+        if byte.is_ascii_whitespace() {
+            output.append(&mut self.held);
+            if self.started {
+                self.whitespace.push(byte);
+                self.lines += usize::from(byte == b'\n');
+            }
+
+            return;
+        }
+
+        if let Some((_, text)) = &mut self.link {
+            text.push(byte);
+        }
+
+        // UTF-8 continuation bytes belong to the held character
+        if (0x80..0xc0).contains(&byte) && !self.held.is_empty() {
+            self.held.push(byte);
+            return;
+        }
+
+        self.started = true;
+        output.append(&mut self.whitespace);
+        output.append(&mut self.held);
+        self.held.push(byte);
+    }
+
+    // X BS X is bold and _ BS X underlined, either way only the second character stays
+    fn backspace(&mut self) {
+        if self.hidden() {
+            return;
+        }
+
+        match self.held.is_empty() {
+            true => {
+                if self.whitespace.pop() == Some(b'\n') {
+                    self.lines -= 1;
+                }
+            }
+            false => self.held.clear(),
+        }
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        mem::take(&mut self.held)
+    }
+
+    fn strip_all(&mut self, input: &[u8]) -> Vec<u8> {
+        let mut output = self.strip(input);
+        output.append(&mut self.finish());
+        output
+    }
+
+    // ESC ] 8 ; PARAMETERS ; URI, an empty uri ends the link
+    fn finish_command_string(&mut self, output: &mut Vec<u8>) {
+        let Some(link) = self.sequence.strip_prefix(b"]8;") else {
+            return;
+        };
+
+        let link = String::from_utf8_lossy(link);
+        let uri = link.split_once(';').map_or("", |(_, uri)| uri).to_string();
+        // Links whose text already is the url are left alone
+        if let Some((open, text)) = self.link.take()
+            && self.keep_links
+            && String::from_utf8_lossy(&text).trim() != open
+        {
+            for byte in format!(" ({open})").bytes() {
+                self.push_text(byte, output);
+            }
+        }
+
+        if !uri.is_empty() {
+            self.links.push((self.lines, uri.clone()));
+            self.link = Some((uri, Vec::new()));
+        }
+    }
+
+    fn strip(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(chunk.len());
+        for &byte in chunk {
+            self.state = match (self.state, byte) {
+                (State::Text, ESCAPE) => State::Escape,
+                (State::Text, b'\r' | BELL | SHIFT_OUT | SHIFT_IN) => State::Text,
+                (State::Text, BACKSPACE) => {
+                    self.backspace();
+                    State::Text
+                }
+                (State::Text, byte) => {
+                    self.push_text(byte, &mut output);
+                    State::Text
+                }
+                (State::Escape, b'[') => {
+                    self.sequence.clear();
+                    State::ControlSequence
+                }
+                (State::Escape, 0x50 | 0x5d | 0x5e | 0x5f) => {
+                    self.sequence.clear();
+                    self.sequence.push(byte);
+                    State::CommandString
+                }
+                (State::Escape | State::EscapeIntermediate, 0x20..=0x2f) => {
+                    State::EscapeIntermediate
+                }
+                (State::EscapeIntermediate, _) => State::Text,
+                // Independent control functions
+                (State::Escape, 0x60..=0x7e) => State::Text,
+                // Not an escape sequence after all
+                (State::Escape, byte) => {
+                    self.push_text(ESCAPE, &mut output);
+                    self.push_text(byte, &mut output);
+                    State::Text
+                }
+                (State::ControlSequence, 0x20..=0x3f) => {
+                    self.sequence.push(byte);
+                    State::ControlSequence
+                }
+                // 1049 and 1047 are the xterm variants of 47
+                (State::ControlSequence, b'h' | b'l')
+                    if matches!(&self.sequence[..], b"?1049" | b"?1047" | b"?47") =>
+                {
+                    self.switch_screen(byte == b'h', &mut output);
+                    State::Text
+                }
+                (State::ControlSequence, b'm') if self.keep_colors && !self.hidden() => {
+                    self.started = true;
+                    output.append(&mut self.whitespace);
+                    output.append(&mut self.held);
+                    output.extend_from_slice(b"\x1b[");
+                    output.append(&mut self.sequence);
+                    output.push(b'm');
+                    State::Text
+                }
+                (State::ControlSequence, _) => State::Text,
+                (State::CommandString, BELL) => {
+                    self.finish_command_string(&mut output);
+                    State::Text
+                }
+                (State::CommandString, ESCAPE) => State::CommandStringEscape,
+                // Only operating system commands can be links, DCS can carry whole sixel images
+                (State::CommandString, byte) => {
+                    if self.sequence[0] == b']' {
+                        self.sequence.push(byte);
+                    }
+
+                    State::CommandString
+                }
+                (State::CommandStringEscape, b'\\') => {
+                    self.finish_command_string(&mut output);
+                    State::Text
+                }
+                (State::CommandStringEscape, _) => State::CommandString,
+            };
+        }
+
+        output
+    }
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    Stripper::new().strip_all(line).is_empty()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+const PROMPT_MARK: &[u8] = b"\x1b]133;A";
+
+// Shells with semantic prompts mark the prompt with OSC 133;A and the start of the command with
+// OSC 133;B, for the others everything after the prompt pattern is the command. Once a mark was
+// seen the pattern is ignored, output can look like a prompt but never carries the mark.
+struct Prompts {
+    pattern: Regex,
+    marked: bool,
+}
+
+impl Prompts {
+    fn new(pattern: &str) -> Result<Self> {
+        Ok(Prompts {
+            pattern: Regex::new(pattern)?,
+            marked: false,
+        })
+    }
+
+    fn command(&mut self, line: &[u8]) -> Option<String> {
+        let clean = |line: &[u8]| {
+            let line = Stripper::new().strip_all(line);
+            String::from_utf8_lossy(&line).trim().to_string()
+        };
+        if find(line, PROMPT_MARK).is_some() {
+            self.marked = true;
+            let Some(start) = find(line, b"\x1b]133;B") else {
+                return Some(String::new());
+            };
+
+            // Cleaning from the start of the sequence drops it along with its terminator
+            return Some(clean(&line[start..]));
+        }
+
+        if self.marked {
+            return None;
+        }
+
+        let text = clean(line);
+        let found = self.pattern.find(&text)?;
+        Some(text[found.end()..].trim().to_string())
+    }
+}
+
+// Each section starts at a prompt, output before the first one is kept as it is
+fn split_commands(
+    raw: &[u8],
+    split: SplitCommands,
+    options: &ScrollbackOptions,
+) -> Result<Vec<u8>> {
+    let mut prompts = Prompts::new(&options.prompt_pattern)?;
+    // The whole selection is at hand, so lines before the first mark aren't guessed at either
+    prompts.marked = find(raw, PROMPT_MARK).is_some();
+    let mut sections = vec![(None, Vec::new())];
+    for line in raw.split_inclusive(|&byte| byte == b'\n') {
+        if let Some(command) = prompts.command(line) {
+            sections.push((Some(command), Vec::new()));
+        }
+
+        sections.last_mut().unwrap().1.extend_from_slice(line);
+    }
+
+    if let SplitCommands::All = split {
+        let mut output = Vec::new();
+        for (number, (command, lines)) in sections.iter().enumerate() {
+            let header = match command.as_deref() {
+                None => String::new(),
+                Some("") => format!("\n── {number} ──\n"),
+                Some(command) => format!("\n── {number}: {command} ──\n"),
+            };
+            output.extend_from_slice(header.as_bytes());
+            output.extend_from_slice(lines);
+        }
+
+        return Ok(output);
+    }
+
+    // Newest first, empty prompts like the one the keybind was pressed from are left out
+    let choices: Vec<_> = sections
+        .iter()
+        .enumerate()
+        .rev()
+        .filter_map(|(number, (command, _))| match command.as_deref() {
+            Some("") | None => None,
+            Some(command) => Some(format!("{number}: {command}")),
+        })
+        .collect();
+    let picked = command::pick(&options.section_picker, &choices.join("\n"))?;
+    let number: usize = picked
+        .split_once(':')
+        .and_then(|(number, _)| number.parse().ok())
+        .ok_or_else(|| eyre!("Unexpected section {picked}"))?;
+    Ok(mem::take(&mut sections[number].1))
+}
+
+// Scrollbacks end at the prompt the keybind was pressed from, so when nothing follows the last
+// prompt the output of the command before it is taken instead
+fn tail(options: &ScrollbackOptions) -> Result<Vec<u8>> {
+    let mut prompts = Prompts::new(&options.prompt_pattern)?;
+    let limit = options.last_lines.unwrap_or(usize::MAX);
+    let mut previous = VecDeque::new();
+    let mut current = VecDeque::new();
+    let mut input = options.input()?;
+    loop {
+        let mut line = Vec::new();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        if options.since_prompt && prompts.command(&line).is_some() {
+            previous = mem::take(&mut current);
+        }
+
+        current.push_back(line);
+        if current.len() > limit {
+            current.pop_front();
+        }
+    }
+
+    let only_prompt = current.iter().skip(1).all(|line| is_blank(line));
+    if options.since_prompt && only_prompt && !previous.is_empty() {
+        current = previous;
+    }
+
+    Ok(current.into_iter().flatten().collect())
+}
+
+fn stream(
+    stripper: &mut Stripper,
+    options: &ScrollbackOptions,
+    mut sink: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    // Only the selected lines are cleaned, which is what makes it fast
+    if options.last_lines.is_some() || options.since_prompt || options.split_commands.is_some() {
+        let mut raw = tail(options)?;
+        if let Some(split) = options.split_commands {
+            raw = split_commands(&raw, split, options)?;
+        }
+
+        return sink(&stripper.strip_all(&raw));
+    }
+
+    let mut chunk = vec![0; 1 << 16];
+    let mut input = options.input()?;
+    loop {
+        let read = input.read(&mut chunk)?;
+        if read == 0 {
+            return sink(&stripper.finish());
+        }
+
+        sink(&stripper.strip(&chunk[..read]))?;
+    }
+}
+
+fn editor_binary(editor_name: &str) -> &str {
+    Path::new(editor_name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(editor_name)
+}
+
+// The interesting output is at the bottom. vim only reads stdin with -, helix has no way to move
+// the cursor from the command line and is left at the top.
+fn end_args(options: &ScrollbackOptions) -> Vec<&str> {
+    if let Some(args) = &options.editor_args {
+        return args.split_whitespace().collect();
+    }
+
+    match editor_binary(&options.editor_name) {
+        "nvim" => vec!["+"],
+        "vim" | "vi" => vec!["+", "-"],
+        "kak" => vec!["-e", "exec ge"],
+        "less" => vec!["+G"],
+        _ => Vec::new(),
+    }
+}
+
+// The editor reads the stream from its stdin, the browser needs a file
+fn open(options: &ScrollbackOptions) -> Result<()> {
+    let ScrollbackOptions {
+        editor_name,
+        keep_colors,
+        ..
+    } = options;
+    let keep_colors = *keep_colors;
+    let html_path = state::runtime_path("scrollback.html");
+    let (mut editor, mut output): (_, Box<dyn Write>) = match keep_colors {
+        Some(KeepColors::Html) => (None, Box::new(File::create(&html_path)?)),
+        _ => {
+            let mut editor = Command::new(editor_name)
+                .args(end_args(options))
+                .stdin(Stdio::piped())
+                .spawn()?;
+            let stdin = editor.stdin.take().unwrap();
+            (Some(editor), Box::new(stdin))
+        }
+    };
+
+    let mut stripper = Stripper::from_options(options);
+    stripper.keep_colors = keep_colors.is_some();
+    let mut html = matches!(keep_colors, Some(KeepColors::Html)).then(html::Html::default);
+    if html.is_some() {
+        output.write_all(html::HEADER.as_bytes())?;
+    }
+
+    stream(&mut stripper, options, |stripped| match &mut html {
+        Some(html) => Ok(output.write_all(&html.convert(stripped))?),
+        None => Ok(output.write_all(stripped)?),
+    })?;
+    if let Some(html) = &html {
+        output.write_all(html.finish())?;
+        output.write_all(html::FOOTER.as_bytes())?;
+    }
+
+    // Closing stdin is what tells the editor the input is complete
+    drop(output);
+    let Some(editor) = &mut editor else {
+        return run_command("xdg-open", [html_path.to_str().unwrap()]);
+    };
+
+    let status = editor.wait()?;
+    if !status.success() {
+        return Err(CommandFailed::new(editor_name, status).into());
+    }
+
+    Ok(())
+}
+
+// Closing punctuation is more likely the sentence's than the url's, unless the url opened it
+fn trim_url(url: &str) -> &str {
+    let mut url = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"']);
+    while url.ends_with(')') && url.matches(')').count() > url.matches('(').count() {
+        url = &url[..url.len() - 1];
+    }
+
+    url
+}
+
+// Lines are handed over whole, a match can straddle two chunks
+fn lines(
+    stripper: &mut Stripper,
+    options: &ScrollbackOptions,
+    mut scan: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let mut line = Vec::new();
+    stream(stripper, options, |stripped| {
+        for &byte in stripped {
+            match byte {
+                b'\n' => {
+                    scan(&String::from_utf8_lossy(&line))?;
+                    line.clear();
+                }
+                byte => line.push(byte),
+            }
+        }
+
+        Ok(())
+    })?;
+    scan(&String::from_utf8_lossy(&line))
+}
+
+// Like grep -C, groups of lines that aren't adjacent are separated by --
+fn grep(options: &ScrollbackOptions, pattern: &str, context: usize) -> Result<()> {
+    let pattern = Regex::new(pattern)?;
+    let mut stdout = io::stdout().lock();
+    let mut before = VecDeque::new();
+    let mut after = 0;
+    let mut number = 0;
+    let mut last_printed = None;
+    let mut stripper = Stripper::from_options(options);
+    lines(&mut stripper, options, |line| {
+        number += 1;
+        if pattern.is_match(line) {
+            let first = number - before.len();
+            if context > 0 && last_printed.is_some_and(|last| last + 1 < first) {
+                writeln!(stdout, "--")?;
+            }
+
+            for line in before.drain(..) {
+                writeln!(stdout, "{line}")?;
+            }
+
+            writeln!(stdout, "{line}")?;
+            last_printed = Some(number);
+            after = context;
+        } else if after > 0 {
+            writeln!(stdout, "{line}")?;
+            last_printed = Some(number);
+            after -= 1;
+        } else if context > 0 {
+            before.push_back(line.to_string());
+            if before.len() > context {
+                before.pop_front();
+            }
+        }
+
+        Ok(())
+    })
+}
+
+// Newest first, that's usually the one being looked for
+fn newest_unique(found: Vec<String>) -> Vec<String> {
+    let mut unique = Vec::new();
+    for item in found.into_iter().rev() {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+
+    unique
+}
+
+fn urls(options: &ScrollbackOptions) -> Result<Vec<String>> {
+    let pattern = Regex::new(r"(?:https?|ftp|file)://[^\s<>\x60\x22]+")?;
+    let mut stripper = Stripper::from_options(options);
+    let mut found = Vec::new();
+    let mut number = 0;
+    lines(&mut stripper, options, |line| {
+        for url in pattern.find_iter(line) {
+            found.push((number, trim_url(url.as_str()).to_string()));
+        }
+
+        number += 1;
+        Ok(())
+    })?;
+    // Link targets go back among the urls in scrollback order, the picker lists the newest first
+    found.append(&mut stripper.links);
+    found.sort_by_key(|(number, _)| *number);
+    Ok(newest_unique(
+        found.into_iter().map(|(_, url)| url).collect(),
+    ))
+}
+
+// Like src/main.rs:12:5 from rustc or tests/a.py:40 from pytest, only files that exist are kept
+fn paths(options: &ScrollbackOptions) -> Result<Vec<String>> {
+    let pattern = Regex::new(r"[\w.~/+-]*[\w+-]:\d+(?::\d+)?")?;
+    let mut found = Vec::new();
+    let mut stripper = Stripper::from_options(options);
+    lines(&mut stripper, options, |line| {
+        for location in pattern.find_iter(line) {
+            let location = location.as_str();
+            let (path, position) = location.split_once(':').unwrap_or_default();
+            // Editors don't expand ~ themselves
+            let path = match path.starts_with("~/") {
+                true => state::home_path(Path::new(path))?,
+                false => PathBuf::from(path),
+            };
+            if path.is_file() {
+                found.push(format!("{}:{position}", path.display()));
+            }
+        }
+
+        Ok(())
+    })?;
+    Ok(newest_unique(found))
+}
+
+// Every editor spells jumping to a line differently
+fn editor_args(editor_name: &str, path: &str, line: &str, column: Option<&str>) -> Vec<String> {
+    let editor = editor_binary(editor_name);
+    let column = column.unwrap_or("1");
+    match editor {
+        "hx" | "helix" | "micro" => vec![format!("{path}:{line}:{column}")],
+        "code" | "codium" => vec!["--goto".to_string(), format!("{path}:{line}:{column}")],
+        "kak" | "emacs" | "emacsclient" => vec![format!("+{line}:{column}"), path.to_string()],
+        "nano" => vec![format!("+{line},{column}"), path.to_string()],
+        "vim" | "nvim" | "vi" => vec![format!("+call cursor({line}, {column})"), path.to_string()],
+        _ => vec![format!("+{line}"), path.to_string()],
+    }
+}
+
+fn jump(options: &ScrollbackOptions, picker: &str) -> Result<()> {
+    let location = command::pick(picker, &paths(options)?.join("\n"))?;
+    let mut parts = location.split(':');
+    let (Some(path), Some(line)) = (parts.next(), parts.next()) else {
+        return Err(eyre!("Unexpected location {location}"));
+    };
+
+    let args = editor_args(&options.editor_name, path, line, parts.next());
+    run_command(&options.editor_name, args.iter().map(String::as_str))
+}
+
+// Long enough to recognise what was copied, notification daemons don't scroll
+const PREVIEW_CHARS: usize = 200;
+
+fn copy(options: &ScrollbackOptions) -> Result<()> {
+    let mut cleaned = Vec::new();
+    stream(&mut Stripper::from_options(options), options, |stripped| {
+        cleaned.extend_from_slice(stripped);
+        Ok(())
+    })?;
+
+    let text = String::from_utf8_lossy(&cleaned);
+    clipboard::copy_text(&text)?;
+    let lines = text.lines().count();
+    // The end is what was just printed
+    let start = text
+        .char_indices()
+        .rev()
+        .nth(PREVIEW_CHARS - 1)
+        .map_or(0, |(index, _)| index);
+    let ellipsis = match start > 0 {
+        true => "…",
+        false => "",
+    };
+    let preview = &text[start..];
+    let message = format!("Copied {lines} lines\n{ellipsis}{preview}");
+    run_command("notify-send", ["Scrollback", &message, "-t", "6000"])
+}
+
+fn capture_path() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("scripts")
+        .join("scrollback-capture.txt")
+}
+
+// diff exits with 1 when the files differ, which isn't a failure here
+fn diff(options: &ScrollbackOptions, pager: Option<&str>) -> Result<()> {
+    let previous = capture_path();
+    fs::create_dir_all(previous.parent().unwrap())?;
+    let current = previous.with_extension("new");
+    let mut file = File::create(&current)?;
+    stream(&mut Stripper::from_options(options), options, |stripped| {
+        Ok(file.write_all(stripped)?)
+    })?;
+
+    if !previous.exists() {
+        fs::rename(&current, &previous)?;
+        println!("Captured the scrollback, the next diff compares against it");
+        return Ok(());
+    }
+
+    let output = Command::new("diff")
+        .args(["--unified", "--label", "previous", "--label", "current"])
+        .args([&previous, &current])
+        .output()?;
+    if output.status.code().is_some_and(|code| code > 1) {
+        return Err(CommandFailed::new("diff", output.status).into());
+    }
+
+    fs::rename(&current, &previous)?;
+    if output.stdout.is_empty() {
+        println!("The scrollback is the same as the previous capture");
+        return Ok(());
+    }
+
+    let mut pager = pager.unwrap_or(&options.editor_name).split_whitespace();
+    let command = pager.next().ok_or_eyre("Empty pager command")?;
+    run_command_with_stdio(command, pager, false, Some(&output.stdout))?;
+    Ok(())
+}
+
+fn log_dir(dir: Option<&Path>) -> Result<PathBuf> {
+    let Some(dir) = dir else {
+        let documents = dirs::document_dir().ok_or_eyre("Cannot determine documents dir")?;
+        return Ok(documents.join("term-logs"));
+    };
+
+    state::home_path(dir)
+}
+
+fn save(options: &ScrollbackOptions, dir: Option<&Path>, open: bool) -> Result<()> {
+    let dir = log_dir(dir)?;
+    fs::create_dir_all(&dir)?;
+    const FMT: &str = "scrollback-%Y-%m-%d-%H:%M:%S.txt";
+    let path = dir.join(Local::now().format(FMT).to_string());
+    let mut file = File::create(&path)?;
+    stream(&mut Stripper::from_options(options), options, |stripped| {
+        Ok(file.write_all(stripped)?)
+    })?;
+
+    let path = path.to_str().unwrap();
+    clipboard::copy_text(path)?;
+    run_command(
+        "notify-send",
+        ["Scrollback", &format!("Saved to {path}"), "-t", "6000"],
+    )?;
+    match open {
+        true => run_command(&options.editor_name, [path]),
+        false => Ok(()),
+    }
+}
+
+pub fn scrollback(action: Option<ScrollbackAction>, options: ScrollbackOptions) -> Result<()> {
+    match action {
+        None => open(&options),
+        Some(ScrollbackAction::Urls { copy, picker }) => {
+            let url = command::pick(&picker, &urls(&options)?.join("\n"))?;
+            match copy {
+                true => clipboard::copy_text(&url),
+                false => command::spawn_detached("xdg-open", [url.as_str()]).map(|_| ()),
+            }
+        }
+        Some(ScrollbackAction::Paths { picker }) => jump(&options, &picker),
+        Some(ScrollbackAction::Grep { pattern, context }) => grep(&options, &pattern, context),
+        Some(ScrollbackAction::Copy) => copy(&options),
+        Some(ScrollbackAction::Diff { pager }) => diff(&options, pager.as_deref()),
+        Some(ScrollbackAction::Save { dir, open }) => save(&options, dir.as_deref(), open),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds the input one byte at a time, splitting every sequence between chunks
+    fn strip_bytewise(stripper: &mut Stripper, input: &[u8]) -> String {
+        let mut output: Vec<u8> = input
+            .iter()
+            .flat_map(|&byte| stripper.strip(&[byte]))
+            .collect();
+        output.append(&mut stripper.finish());
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn escapes_split_across_chunks() {
+        let input = b"\x1b[1;31mred\x1b[0m \x1b]0;title\x07plain\x1bPsixel\x1b\\";
+        let whole = String::from_utf8(Stripper::new().strip_all(input)).unwrap();
+        assert_eq!(whole, "red plain");
+        assert_eq!(strip_bytewise(&mut Stripper::new(), input), whole);
+    }
+
+    #[test]
+    fn colors_kept_across_chunks() {
+        let mut stripper = Stripper::new();
+        stripper.keep_colors = true;
+        let output = strip_bytewise(&mut stripper, b"\x1b[32mok\x1b[0m");
+        assert_eq!(output, "\x1b[32mok\x1b[0m");
+    }
+
+    #[test]
+    fn links_with_either_terminator() {
+        for terminator in ["\x07", "\x1b\\"] {
+            let input =
+                format!("\x1b]8;;https://example.com{terminator}docs\x1b]8;;{terminator} end");
+            let mut stripper = Stripper::new();
+            assert_eq!(strip_bytewise(&mut stripper, input.as_bytes()), "docs end");
+            assert_eq!(stripper.links, [(0, "https://example.com".to_string())]);
+        }
+    }
+
+    #[test]
+    fn kept_links_follow_their_text() {
+        let mut stripper = Stripper::new();
+        stripper.keep_links = true;
+        let input = b"\x1b]8;;https://example.com\x1b\\docs\x1b]8;;\x1b\\ end";
+        assert_eq!(
+            strip_bytewise(&mut stripper, input),
+            "docs (https://example.com) end"
+        );
+    }
+
+    #[test]
+    fn links_whose_text_is_the_url_are_left_alone() {
+        let mut stripper = Stripper::new();
+        stripper.keep_links = true;
+        let input = b"\x1b]8;id=1;https://a.b\x1b\\https://a.b\x1b]8;;\x1b\\";
+        assert_eq!(strip_bytewise(&mut stripper, input), "https://a.b");
+    }
+
+    #[test]
+    fn overstrike_shifts_and_bells() {
+        let input = b"B\x08Bold _\x08u\x0e\x0f\x07";
+        assert_eq!(strip_bytewise(&mut Stripper::new(), input), "Bold u");
+    }
+
+    #[test]
+    fn character_set_designations() {
+        let input = b"\x1b(0lqk\x1b(B\x1b[m plain\x1b)0";
+        assert_eq!(strip_bytewise(&mut Stripper::new(), input), "lqk plain");
+    }
+
+    const SCREENS: &[u8] = b"shell\n\x1b[?1049hvim\x1b[?1049l\nback\x1b[?47hless\x1b[?47l";
+
+    #[test]
+    fn alt_screen_drop() {
+        let mut stripper = Stripper::new();
+        stripper.alt_screen = AltScreen::Drop;
+        assert_eq!(strip_bytewise(&mut stripper, SCREENS), "shell\n\nback");
+    }
+
+    #[test]
+    fn alt_screen_only() {
+        let mut stripper = Stripper::new();
+        stripper.alt_screen = AltScreen::Only;
+        assert_eq!(strip_bytewise(&mut stripper, SCREENS), "vim\n\nless");
+    }
+
+    #[test]
+    fn default_prompt_pattern() {
+        let mut prompts = Prompts::new(PROMPT_PATTERN).unwrap();
+        let mut command = |line: &str| prompts.command(line.as_bytes());
+        assert_eq!(
+            command("user@host:~/src$ cargo test").unwrap(),
+            "cargo test"
+        );
+        assert_eq!(command("[user@host src]$ ls -l").unwrap(), "ls -l");
+        assert_eq!(command("~/src ❯ make").unwrap(), "make");
+        assert_eq!(command("/etc # ").unwrap(), "");
+        for output in [
+            "100% done",
+            "# comment",
+            "50%",
+            "[ 50%] Building",
+            "$ not mine",
+        ] {
+            assert!(command(output).is_none(), "{output:?} taken for a prompt");
+        }
+    }
+
+    #[test]
+    fn prompt_marks_win_over_the_pattern() {
+        let mut prompts = Prompts::new(PROMPT_PATTERN).unwrap();
+        let marked = b"\x1b]133;A\x07~ $ \x1b]133;B\x07cargo build\n";
+        assert_eq!(prompts.command(marked).unwrap(), "cargo build");
+        assert!(prompts.command(b"user@host:~$ looks like one\n").is_none());
+    }
+
+    #[test]
+    fn links_remember_their_line() {
+        let mut stripper = Stripper::new();
+        let input = b"\n\nfirst\nsecond \x1b]8;;https://a.b\x07here\x1b]8;;\x07\n\nthird";
+        strip_bytewise(&mut stripper, input);
+        assert_eq!(stripper.links, [(1, "https://a.b".to_string())]);
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt::Write as _;
+
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+pub const HEADER: &str = "<!DOCTYPE html>
+<html><head><meta charset=\"utf-8\"><title>Scrollback</title></head>
+<body style=\"background: #1e1e1e; color: #e5e5e5\"><pre>";
+pub const FOOTER: &str = "</pre></body></html>\n";
+
+// The 6x6x6 cube and the grey ramp follow the 16 named colors
+fn indexed(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..16 => PALETTE[usize::from(index)],
+        16..232 => {
+            let level = |value: u8| match value {
+                0 => 0,
+                value => 55 + value * 40,
+            };
+            let index = index - 16;
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        232.. => {
+            let grey = 8 + (index - 232) * 10;
+            (grey, grey, grey)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Style {
+    foreground: Option<(u8, u8, u8)>,
+    background: Option<(u8, u8, u8)>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl Style {
+    // 38 and 48 take either 5;INDEX or 2;R;G;B
+    fn extended(parameters: &mut impl Iterator<Item = u8>) -> Option<(u8, u8, u8)> {
+        match parameters.next()? {
+            5 => Some(indexed(parameters.next()?)),
+            2 => Some((parameters.next()?, parameters.next()?, parameters.next()?)),
+            _ => None,
+        }
+    }
+
+    fn apply(&mut self, sequence: &str) {
+        let mut parameters = sequence
+            .split(';')
+            .map(|parameter| parameter.parse::<u16>().unwrap_or(0).min(255) as u8);
+        while let Some(parameter) = parameters.next() {
+            match parameter {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.foreground = Some(PALETTE[usize::from(parameter - 30)]),
+                38 => self.foreground = Style::extended(&mut parameters),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some(PALETTE[usize::from(parameter - 40)]),
+                48 => self.background = Style::extended(&mut parameters),
+                49 => self.background = None,
+                90..=97 => self.foreground = Some(PALETTE[usize::from(parameter - 82)]),
+                100..=107 => self.background = Some(PALETTE[usize::from(parameter - 92)]),
+                _ => {}
+            }
+        }
+    }
+
+    fn css(&self) -> String {
+        let mut css = String::new();
+        if let Some((red, green, blue)) = self.foreground {
+            let _ = write!(css, "color: #{red:02x}{green:02x}{blue:02x};");
+        }
+
+        if let Some((red, green, blue)) = self.background {
+            let _ = write!(css, "background: #{red:02x}{green:02x}{blue:02x};");
+        }
+
+        if self.bold {
+            css.push_str("font-weight: bold;");
+        }
+
+        if self.italic {
+            css.push_str("font-style: italic;");
+        }
+
+        if self.underline {
+            css.push_str("text-decoration: underline;");
+        }
+
+        css
+    }
+}
+
+// Turns text with SGR sequences into spans. The stripper hands over whole sequences, so only the
+// style has to carry over between chunks.
+#[derive(Default)]
+pub struct Html {
+    style: Style,
+}
+
+impl Html {
+    pub fn convert(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(chunk.len());
+        let mut rest = chunk;
+        while let Some((&byte, tail)) = rest.split_first() {
+            rest = tail;
+            match byte {
+                // Only SGR is styling, the ESC of anything else like ESC 7 is dropped
+                0x1b => {
+                    let Some(parameters) = rest.strip_prefix(b"[") else {
+                        continue;
+                    };
+                    let end = parameters
+                        .iter()
+                        .position(|&byte| !(byte.is_ascii_digit() || byte == b';'));
+                    let Some(end) = end.filter(|&end| parameters[end] == b'm') else {
+                        continue;
+                    };
+
+                    let sequence = String::from_utf8_lossy(&parameters[..end]);
+                    rest = &parameters[end + 1..];
+                    let old = self.style;
+                    self.style.apply(&sequence);
+                    if self.style == old {
+                        continue;
+                    }
+
+                    if old != Style::default() {
+                        output.extend_from_slice(b"</span>");
+                    }
+
+                    if self.style != Style::default() {
+                        let span = format!("<span style=\"{}\">", self.style.css());
+                        output.extend_from_slice(span.as_bytes());
+                    }
+                }
+                b'<' => output.extend_from_slice(b"&lt;"),
+                b'>' => output.extend_from_slice(b"&gt;"),
+                b'&' => output.extend_from_slice(b"&amp;"),
+                byte => output.push(byte),
+            }
+        }
+
+        output
+    }
+
+    pub fn finish(&self) -> &'static [u8] {
+        match self.style == Style::default() {
+            true => b"",
+            false => b"</span>",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_and_escaping() {
+        let mut html = Html::default();
+        let mut output = html.convert(b"a<b \x1b[1;31mred\x1b[39m bold\x1b[0m &");
+        output.extend_from_slice(html.finish());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a&lt;b <span style=\"color: #cd0000;font-weight: bold;\">red</span>\
+             <span style=\"font-weight: bold;\"> bold</span> &amp;"
+        );
+    }
+
+    #[test]
+    fn style_carries_over_between_chunks() {
+        let mut html = Html::default();
+        let mut output = html.convert(b"\x1b[38;5;196mone");
+        output.extend(html.convert(b"two\x1b[48;2;1;2;3m"));
+        output.extend_from_slice(html.finish());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<span style=\"color: #ff0000;\">onetwo</span>\
+             <span style=\"color: #ff0000;background: #010203;\"></span>"
+        );
+    }
+
+    #[test]
+    fn only_sgr_is_styling() {
+        let mut html = Html::default();
+        let output = html.convert(b"\x1b7saved\x1b=keypad \x1b[2Jmore");
+        assert_eq!(String::from_utf8(output).unwrap(), "7saved=keypad [2Jmore");
+    }
+}
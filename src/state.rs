@@ -0,0 +1,31 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{Result, eyre::OptionExt};
+
+// Gone after logout, which is when pids and sockets stop meaning anything
+pub fn runtime_path(name: &str) -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join(format!("scripts-{name}"))
+}
+
+// Renaming is atomic, so anything polling the file never reads half of it
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+// Configured dirs are relative to the home dir, with or without a leading ~
+pub fn home_path(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+
+    let home = dirs::home_dir().ok_or_eyre("Cannot determine home dir")?;
+    Ok(home.join(path.strip_prefix("~").unwrap_or(path)))
+}
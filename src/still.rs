@@ -0,0 +1,85 @@
+use std::{env, fs, thread, time::Duration};
+
+use color_eyre::{Result, eyre::eyre};
+use swayipc::Output;
+
+use crate::{command::spawn_detached, geometry::Rect};
+
+// The output imv went fullscreen on, which is usually but not always the focused one
+fn viewer_output(pid: u32) -> Result<Output> {
+    for _ in 0..20 {
+        let mut connection = swayipc::Connection::new()?;
+        let tree = connection.get_tree()?;
+        let viewer = tree
+            .iter()
+            .find(|node| node.pid.is_some_and(|node_pid| node_pid as u32 == pid));
+        if let Some(viewer) = viewer {
+            let (x, y) = (
+                viewer.rect.x + viewer.rect.width / 2,
+                viewer.rect.y + viewer.rect.height / 2,
+            );
+            return connection
+                .get_outputs()?
+                .into_iter()
+                .find(|output| {
+                    let rect = output.rect;
+                    (rect.x..rect.x + rect.width).contains(&x)
+                        && (rect.y..rect.y + rect.height).contains(&y)
+                })
+                .ok_or_else(|| eyre!("imv is not on any output"));
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    Err(eyre!("imv did not open a window"))
+}
+
+// Runs `select` while the image is shown fullscreen by imv, along with the output it's shown on
+pub fn select_over<T>(png: &[u8], select: impl FnOnce() -> Result<T>) -> Result<(T, Output)> {
+    let path = env::temp_dir().join("scripts-still.png");
+    fs::write(&path, png)?;
+    let mut viewer = spawn_detached("imv", ["-f", "-s", "shrink", path.to_str().unwrap()])?;
+    let selected = viewer_output(viewer.id()).and_then(|output| {
+        // Give imv a moment to go fullscreen before slurp grabs the pointer
+        thread::sleep(Duration::from_millis(200));
+        Ok((select()?, output))
+    });
+    viewer.kill()?;
+    viewer.wait()?;
+    fs::remove_file(&path)?;
+    selected
+}
+
+// Maps a selection made over an image shown by `select_over` to pixels of that image, given the
+// output it was shown on. slurp reports logical coordinates while imv centers the image and only
+// ever scales it down, so the scale the image was captured at doesn't matter.
+pub fn to_pixels(
+    selection: Rect,
+    output: &Output,
+    width: u32,
+    height: u32,
+) -> (u32, u32, u32, u32) {
+    let scale = output.scale.unwrap_or(1.0);
+    let screen_width = output.rect.width as f64 * scale;
+    let screen_height = output.rect.height as f64 * scale;
+    let shrink = (screen_width / width as f64)
+        .min(screen_height / height as f64)
+        .min(1.0);
+    let offset_x = (screen_width - width as f64 * shrink) / 2.0;
+    let offset_y = (screen_height - height as f64 * shrink) / 2.0;
+
+    let to_x = |logical: i32| {
+        let physical = (logical - output.rect.x) as f64 * scale;
+        (((physical - offset_x) / shrink).round().max(0.0) as u32).min(width)
+    };
+    let to_y = |logical: i32| {
+        let physical = (logical - output.rect.y) as f64 * scale;
+        (((physical - offset_y) / shrink).round().max(0.0) as u32).min(height)
+    };
+    let x = to_x(selection.x);
+    let y = to_y(selection.y);
+    let right = to_x(selection.x + selection.width);
+    let bottom = to_y(selection.y + selection.height);
+    (x, y, right - x, bottom - y)
+}
@@ -0,0 +1,22 @@
+use color_eyre::Result;
+
+// Paste hosts like 0x0.st expect the file as a multipart form field
+pub fn upload(url: &str, file_name: &str, mime: &str, bytes: &[u8]) -> Result<String> {
+    const BOUNDARY: &str = "----scripts-upload-boundary-7d3f1a9c";
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: {mime}\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+
+    let response = ureq::post(url)
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .send(&body)?
+        .body_mut()
+        .read_to_string()?;
+    Ok(response.trim().to_string())
+}
@@ -0,0 +1,119 @@
+use clap::Subcommand;
+use color_eyre::{Result, eyre::eyre};
+
+use crate::{
+    command::{not_found, run_command, run_command_with_stdio},
+    notification,
+};
+
+#[derive(Subcommand)]
+pub enum VolumeAction {
+    Up,
+    Down,
+    Mute,
+}
+
+pub struct Level {
+    pub percent: u32,
+    pub muted: bool,
+}
+
+// wpctl prints "Volume: 0.45" with " [MUTED]" appended when muted
+pub fn wpctl_level(target: &str) -> Result<Level> {
+    let output = run_command_with_stdio("wpctl", ["get-volume", target], true, None)?;
+    let output = String::from_utf8(output)?;
+    let volume = output
+        .split_whitespace()
+        .nth(1)
+        .and_then(|volume| volume.parse::<f64>().ok())
+        .ok_or_else(|| eyre!("Unexpected wpctl output {output}"))?;
+    Ok(Level {
+        percent: (volume * 100.0).round() as u32,
+        muted: output.contains("[MUTED]"),
+    })
+}
+
+// pactl reports every channel, the first one is close enough
+fn pactl_level() -> Result<Level> {
+    let volume =
+        run_command_with_stdio("pactl", ["get-sink-volume", "@DEFAULT_SINK@"], true, None)?;
+    let volume = String::from_utf8(volume)?;
+    let percent = volume
+        .split_whitespace()
+        .find_map(|word| word.strip_suffix('%')?.parse().ok())
+        .ok_or_else(|| eyre!("Unexpected pactl output {volume}"))?;
+    let mute = run_command_with_stdio("pactl", ["get-sink-mute", "@DEFAULT_SINK@"], true, None)?;
+    Ok(Level {
+        percent,
+        muted: String::from_utf8(mute)?.contains("yes"),
+    })
+}
+
+fn wpctl(action: &VolumeAction, step: u32, limit: u32) -> Result<Level> {
+    let limit = format!("{}", f64::from(limit) / 100.0);
+    match action {
+        VolumeAction::Up => run_command(
+            "wpctl",
+            [
+                "set-volume",
+                "-l",
+                &limit,
+                "@DEFAULT_AUDIO_SINK@",
+                &format!("{step}%+"),
+            ],
+        )?,
+        VolumeAction::Down => run_command(
+            "wpctl",
+            ["set-volume", "@DEFAULT_AUDIO_SINK@", &format!("{step}%-")],
+        )?,
+        VolumeAction::Mute => run_command("wpctl", ["set-mute", "@DEFAULT_AUDIO_SINK@", "toggle"])?,
+    }
+
+    wpctl_level("@DEFAULT_AUDIO_SINK@")
+}
+
+// pactl has no limit of its own, so raising is clamped by hand. A volume already past the limit
+// is left alone rather than lowered.
+fn pactl(action: &VolumeAction, step: u32, limit: u32) -> Result<Level> {
+    match action {
+        VolumeAction::Up => {
+            let current = pactl_level()?.percent;
+            let percent = (current + step).min(limit).max(current);
+            run_command(
+                "pactl",
+                ["set-sink-volume", "@DEFAULT_SINK@", &format!("{percent}%")],
+            )?
+        }
+        VolumeAction::Down => run_command(
+            "pactl",
+            ["set-sink-volume", "@DEFAULT_SINK@", &format!("-{step}%")],
+        )?,
+        VolumeAction::Mute => run_command("pactl", ["set-sink-mute", "@DEFAULT_SINK@", "toggle"])?,
+    }
+
+    pactl_level()
+}
+
+fn icon(level: &Level) -> &'static str {
+    match level.percent {
+        _ if level.muted => "audio-volume-muted",
+        0 => "audio-volume-muted",
+        1..=33 => "audio-volume-low",
+        34..=66 => "audio-volume-medium",
+        _ => "audio-volume-high",
+    }
+}
+
+// wpctl comes with PipeWire, pactl is the fallback on plain PulseAudio
+pub fn volume(action: VolumeAction, step: u32, limit: u32) -> Result<()> {
+    let level = match wpctl(&action, step, limit) {
+        Err(error) if not_found(&error) => pactl(&action, step, limit),
+        result => result,
+    }?;
+
+    let summary = match level.muted {
+        true => "Volume muted".to_string(),
+        false => format!("Volume {}%", level.percent),
+    };
+    notification::osd(&summary, "volume", icon(&level), level.percent)
+}